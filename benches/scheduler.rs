@@ -0,0 +1,56 @@
+// compares the scheduler with the full scan lapse_time used to do, run with `cargo bench`
+#![feature(test)]
+
+extern crate autismarchia_back;
+extern crate test;
+
+use test::Bencher;
+use autismarchia_back::foundation::GameState;
+use autismarchia_back::player::command::Command;
+use autismarchia_back::state::object::Idx;
+use autismarchia_back::state::world::World;
+use autismarchia_back::utils::ipoint::IPoint;
+
+// every entity on every level, as lapse_time did before the scheduler
+fn linear_scan(world: &World) -> Option<Idx> {
+    let entities = || world.levels().values().flat_map(|l| l.get_entities());
+    let interval = entities().map(|e| e.get_cooldown()).min()?;
+    entities()
+        .filter(|e| e.get_cooldown() == interval)
+        .map(|e| e.idx())
+        .min()
+}
+
+fn state(x: i32, y: i32) -> GameState {
+    GameState::new(IPoint { x, y }).unwrap()
+}
+
+#[bench]
+fn linear_scan_60x40(b: &mut Bencher) {
+    let state = state(60, 40);
+    b.iter(|| linear_scan(&state.game));
+}
+
+#[bench]
+fn scheduler_60x40(b: &mut Bencher) {
+    let mut state = state(60, 40);
+    b.iter(|| state.game.lapse_time().map(|e| e.idx()));
+}
+
+#[bench]
+fn linear_scan_200x200(b: &mut Bencher) {
+    let state = state(200, 200);
+    b.iter(|| linear_scan(&state.game));
+}
+
+#[bench]
+fn scheduler_200x200(b: &mut Bencher) {
+    let mut state = state(200, 200);
+    b.iter(|| state.game.lapse_time().map(|e| e.idx()));
+}
+
+#[bench]
+fn player_turn_200x200(b: &mut Bencher) {
+    let mut state = state(200, 200);
+    b.iter(|| state.submit_command(Command::Wait));
+}
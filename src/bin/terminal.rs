@@ -0,0 +1,120 @@
+// plays the game in a terminal: type keys (w, a, s, d) and press enter, q quits
+// usage: terminal [--ascii] [config.json]
+
+extern crate autismarchia_back;
+extern crate serde_json;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, Read};
+use std::process;
+use autismarchia_back::foundation::GameState;
+use autismarchia_back::design::config::GameConfig;
+use autismarchia_back::error::{GameError, GameResult};
+use autismarchia_back::player::player::View;
+use autismarchia_back::player::lifecycle::GameStatus;
+use autismarchia_back::state::object::{Icon, Pixel};
+use autismarchia_back::utils::ipoint::IPoint;
+
+fn glyph(icon: Icon) -> char {
+    match icon {
+        Icon::Wall => '#',
+        Icon::Player => '@',
+        Icon::Enemy => 'e',
+        Icon::Floor => '.',
+        Icon::Empty => ' ',
+    }
+}
+
+fn render_pixel(pixel: Pixel, color: bool) -> String {
+    if color {
+        let c = pixel.1;
+        format!("\x1b[38;2;{};{};{}m{}", c.0, c.1, c.2, glyph(pixel.0))
+    } else {
+        glyph(pixel.0).to_string()
+    }
+}
+
+fn render(view: &View, color: bool) -> String {
+    let mut result = String::new();
+    for y in 0..view.size.y {
+        for x in 0..view.size.x {
+            result.push_str(&render_pixel(view.pixel(IPoint { x, y }), color));
+        }
+        if color {
+            result.push_str("\x1b[0m");
+        }
+        result.push('\n');
+    }
+    result
+}
+
+fn draw(state: &GameState, color: bool) -> GameResult<()> {
+    let view = state.owner().build_view(&state.game)?;
+    if color {
+        // clear screen and move the cursor home
+        print!("\x1b[2J\x1b[H");
+    }
+    print!("{}", render(&view, color));
+    let messages = state.owner().get_messages();
+    for message in messages.iter().skip(messages.len().max(5) - 5) {
+        println!("{}", message);
+    }
+    Ok(())
+}
+
+fn read_config(path: &str) -> Result<GameConfig, String> {
+    let mut json = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut json))
+        .map_err(|e| format!("can not read {}: {}", path, e))?;
+    serde_json::from_str(&json).map_err(|e| format!("can not parse {}: {}", path, e))
+}
+
+fn run(config: GameConfig, color: bool) -> GameResult<()> {
+    let mut state = GameState::from_config(config)?;
+    draw(&state, color)?;
+
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = line.map_err(|e| GameError::InvalidString { message: e.to_string() })?;
+        for key in line.chars() {
+            if key == 'q' {
+                return Ok(());
+            }
+            state.process_key(&key.to_string())?;
+            match state.status {
+                GameStatus::Playing => (),
+                GameStatus::Dead { .. } => {
+                    draw(&state, color)?;
+                    println!("game over");
+                    return Ok(());
+                }
+                GameStatus::Won => {
+                    draw(&state, color)?;
+                    println!("you won");
+                    return Ok(());
+                }
+            }
+        }
+        draw(&state, color)?;
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let color = !args.iter().any(|a| a == "--ascii");
+    let config = match args.iter().find(|a| !a.starts_with("--")) {
+        None => GameConfig::default(),
+        Some(path) => read_config(path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(2);
+        }),
+    };
+
+    if let Err(e) = run(config, color) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
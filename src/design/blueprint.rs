@@ -1,17 +1,21 @@
 extern crate rand;
 
 use core::cmp;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use rand::Rng;
-use rand::XorShiftRng;
+use utils::xorshift::XorShift;
 use rand::SeedableRng;
 use utils::ipoint::IPoint;
 use utils::irange::IRange;
 use utils::pointrng::PointRng;
 use state::world::World;
 use state::level::Level;
-use objects::wall::Wall;
-use objects::floor::Floor;
+use objects::wall::wall;
+use objects::floor::floor;
+use design::config::{GameConfig, PopulationConfig};
+use design::templates::CreatureTemplate;
+use state::object::Idx;
+use error::{GameError, GameResult};
 
 #[derive(Debug)]
 pub enum Tile {
@@ -48,28 +52,26 @@ pub struct Blueprint {
     pub size: IPoint,
     pub rooms: Vec<IRange>,
     pub tiles: TileMap,
-    pub random: XorShiftRng
+    pub random: XorShift
 }
 impl Blueprint {
-    pub fn example(size: IPoint) -> Blueprint {
-        let mut bp = Blueprint::new(size);
-        for _ in 0..7 {
-            bp.try_add_room(IPoint { x: 3, y: 3 }.range(IPoint { x: 12, y: 12 }), 5);
-        }
-        for _ in 0..4 {
-            bp.try_add_room(IPoint { x: 1, y: 1 }.range(IPoint { x: 2, y: 2 }), 5);
+    pub fn from_config(config: &GameConfig) -> Blueprint {
+        let mut bp = Blueprint::new(config.size, config.generator_seed);
+        for room in config.rooms.iter() {
+            for _ in 0..room.count {
+                bp.try_add_room(room.min_size.range(room.max_size), room.min_distance);
+            }
         }
         bp.build_rooms();
         bp.connect_tree();
         bp
     }
-    pub fn new(size: IPoint) -> Blueprint {
-        let seed: [u32; 4] = [2, 3, 6, 5];
+    pub fn new(size: IPoint, seed: [u32; 4]) -> Blueprint {
         Blueprint {
             size,
             rooms: Vec::new(),
             tiles: HashMap::new(),
-            random: XorShiftRng::from_seed(seed),
+            random: XorShift::from_seed(seed),
         }
     }
     pub fn build_rooms(&mut self) {
@@ -152,17 +154,83 @@ impl Blueprint {
         }
     }
 
-    pub fn level_from_blueprint<'a>(&self, world: &'a mut World) -> &'a mut Level {
+    pub fn level_from_blueprint<'a>(&self, world: &'a mut World) -> GameResult<&'a mut Level> {
         let mut level = Level::new(world.next_id(), self.size);
         for point in self.size.zrange().iter() {
             if self.tiles.get(&point).is_none() {
-                let object = Wall::new(world.next_id());
-                level.add_entity(Box::new(object), point);
+                level.add_entity(wall(world.next_id()), point)?;
             } else {
-                let object = Floor::new(world.next_id());
-                level.add_entity(Box::new(object), point);
+                level.add_entity(floor(world.next_id()), point)?;
             }
         }
         world.add_level(level)
     }
+
+    // steps from start to every tile that can be walked to
+    fn walking_distances(level: &Level, start: IPoint) -> HashMap<IPoint, i32> {
+        let mut distances = HashMap::new();
+        let mut queue = VecDeque::new();
+        distances.insert(start, 0);
+        queue.push_back(start);
+        while let Some(p) = queue.pop_front() {
+            let steps = distances[&p] + 1;
+            for next in p.neumann_surrounding() {
+                if !level.is_blocking(next) && !distances.contains_key(&next) {
+                    distances.insert(next, steps);
+                    queue.push_back(next);
+                }
+            }
+        }
+        distances
+    }
+
+    // places monsters in the rooms of a level built from this blueprint, visiting the rooms
+    // in random order until the budget runs out; returns them in placing order
+    pub fn populate(&mut self, world: &mut World, level: Idx, population: &PopulationConfig,
+                    start: IPoint) -> GameResult<Vec<Idx>> {
+        let mut placements = Vec::new();
+        {
+            let lvl = world.get_level(level).ok_or(GameError::MissingLevel { idx: level })?;
+            let templates: Vec<&CreatureTemplate> = population.templates.iter()
+                .map(|name| world.templates().get(name))
+                .collect::<GameResult<_>>()?;
+            let distances = Blueprint::walking_distances(lvl, start);
+            let mut budget = population.budget;
+            let mut taken = HashSet::new();
+            let mut rooms = self.rooms.clone();
+            self.random.shuffle(&mut rooms);
+            'rooms: for room in rooms {
+                // rounded up, so small rooms get monsters too
+                let count = (room.iter().count() as f32 * population.density).ceil() as usize;
+                // tiles the player can not walk to are far enough
+                let mut free: Vec<IPoint> = room.iter()
+                    .filter(|p| !lvl.is_blocking(*p) && !taken.contains(p))
+                    .filter(|p| distances.get(p).map_or(true, |d| *d >= population.min_player_distance))
+                    .collect();
+                self.random.shuffle(&mut free);
+                for position in free.into_iter().take(count) {
+                    let affordable: Vec<&CreatureTemplate> = templates.iter()
+                        .filter(|t| t.difficulty <= budget)
+                        .cloned()
+                        .collect();
+                    if affordable.is_empty() {
+                        break 'rooms;
+                    }
+                    let template = affordable[self.random.gen_range(0, affordable.len())];
+                    budget -= template.difficulty;
+                    taken.insert(position);
+                    placements.push((template.name.clone(), position));
+                }
+            }
+        }
+        let mut monsters = Vec::new();
+        for (name, position) in placements {
+            let idx = world.next_id();
+            let bundle = world.templates().build(&name, idx)?;
+            world.get_mut_level(level).ok_or(GameError::MissingLevel { idx: level })?
+                .add_entity(bundle, position)?;
+            monsters.push(idx);
+        }
+        Ok(monsters)
+    }
 }
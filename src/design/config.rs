@@ -0,0 +1,175 @@
+use utils::ipoint::IPoint;
+use state::level::SimulationPolicy;
+use design::templates::{AiProfile, CreatureTemplate, Templates};
+use state::faction::{Factions, Relationship};
+
+// bigger levels and more rooms take too long to generate, or run out of memory
+pub const MAX_SIZE: i32 = 256;
+pub const MAX_ROOMS: u32 = 100;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct GameConfig {
+    pub size: IPoint,
+    pub world_seed: [u32; 4],
+    pub generator_seed: [u32; 4],
+    pub rooms: Vec<RoomConfig>,
+    // player turns kept for undo, 0 disables it
+    pub undo_limit: usize,
+    // how generated levels are simulated while no player is on them
+    pub simulation: SimulationPolicy,
+    // time between wandering monsters spawning on the first level, 0 for none
+    pub wandering_monsters: i64,
+    // the creatures of the game, replacing the builtin ones when given
+    pub creatures: Vec<CreatureTemplate>,
+    // templates players and wandering monsters are made from
+    pub player_template: String,
+    pub wandering_template: String,
+    // monsters placed in the rooms of generated levels
+    pub population: PopulationConfig,
+    // how the factions of the creatures stand at the start
+    pub relations: Vec<Relationship>,
+}
+
+// rules for placing monsters in rooms, see Blueprint::populate
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct PopulationConfig {
+    // share of a room's tiles that may hold a monster, rounded up
+    pub density: f32,
+    // no monster starts fewer steps away from the player than this, walking around walls
+    pub min_player_distance: i32,
+    // sum of the difficulties of the monsters of a level, 0 places none
+    pub budget: u32,
+    // creatures picked from
+    pub templates: Vec<String>,
+}
+
+impl Default for PopulationConfig {
+    fn default() -> PopulationConfig {
+        PopulationConfig {
+            density: 0.05,
+            min_player_distance: 8,
+            budget: 6,
+            templates: vec!["monster".to_string()],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct RoomConfig {
+    pub count: u32,
+    pub min_size: IPoint,
+    pub max_size: IPoint,
+    pub min_distance: i32,
+}
+
+impl Default for GameConfig {
+    fn default() -> GameConfig {
+        GameConfig {
+            size: IPoint { x: 60, y: 40 },
+            world_seed: [1, 2, 3, 4],
+            generator_seed: [2, 3, 6, 5],
+            rooms: vec![
+                RoomConfig {
+                    count: 7,
+                    min_size: IPoint { x: 3, y: 3 },
+                    max_size: IPoint { x: 12, y: 12 },
+                    min_distance: 5,
+                },
+                RoomConfig {
+                    count: 4,
+                    min_size: IPoint { x: 1, y: 1 },
+                    max_size: IPoint { x: 2, y: 2 },
+                    min_distance: 5,
+                },
+            ],
+            undo_limit: 0,
+            simulation: SimulationPolicy::Full,
+            wandering_monsters: 0,
+            creatures: Templates::builtin(),
+            player_template: "player".to_string(),
+            wandering_template: "monster".to_string(),
+            population: PopulationConfig::default(),
+            relations: Factions::builtin(),
+        }
+    }
+}
+
+impl GameConfig {
+    pub fn with_size(size: IPoint) -> GameConfig {
+        GameConfig { size, ..GameConfig::default() }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.size.x <= 0 || self.size.y <= 0 {
+            return Err(format!("size must be positive, got {}", self.size));
+        }
+        if self.size.x > MAX_SIZE || self.size.y > MAX_SIZE {
+            return Err(format!("size can not exceed {} on either side, got {}", MAX_SIZE, self.size));
+        }
+        if self.world_seed.iter().all(|&x| x == 0) {
+            return Err("world_seed can not be all zeros".to_string());
+        }
+        if self.generator_seed.iter().all(|&x| x == 0) {
+            return Err("generator_seed can not be all zeros".to_string());
+        }
+        if self.wandering_monsters < 0 {
+            return Err(format!("wandering_monsters can not be negative, got {}", self.wandering_monsters));
+        }
+        let mut rooms: u32 = 0;
+        for (i, room) in self.rooms.iter().enumerate() {
+            rooms = rooms.saturating_add(room.count);
+            if rooms > MAX_ROOMS {
+                return Err(format!("rooms[{}].count makes more than {} rooms in all, got {}", i, MAX_ROOMS, room.count));
+            }
+            if room.min_size.x < 1 || room.min_size.y < 1 {
+                return Err(format!("rooms[{}].min_size must be at least 1, got {}", i, room.min_size));
+            }
+            // sizes are drawn from the half-open range [min_size, max_size)
+            if room.min_size.x >= room.max_size.x || room.min_size.y >= room.max_size.y {
+                return Err(format!("rooms[{}].max_size must be greater than min_size, got {}", i, room.max_size));
+            }
+            if room.max_size.x > self.size.x || room.max_size.y > self.size.y {
+                return Err(format!("rooms[{}].max_size can not exceed the map size, got {}", i, room.max_size));
+            }
+        }
+        let templates = Templates::new(&self.creatures)?;
+        match templates.get(&self.player_template) {
+            Err(_) => return Err(format!("player_template {} is not one of the creatures", self.player_template)),
+            Ok(t) if t.ai != AiProfile::Player =>
+                return Err(format!("player_template {} must have the player ai", self.player_template)),
+            Ok(_) => (),
+        }
+        if self.wandering_monsters > 0 {
+            match templates.get(&self.wandering_template) {
+                Err(_) => return Err(format!("wandering_template {} is not one of the creatures", self.wandering_template)),
+                Ok(t) if t.ai == AiProfile::Player =>
+                    return Err(format!("wandering_template {} can not have the player ai", self.wandering_template)),
+                Ok(_) => (),
+            }
+        }
+        Factions::new(&self.relations)?;
+        let population = &self.population;
+        if !(population.density >= 0.0 && population.density <= 1.0) {
+            return Err(format!("population.density must be between 0 and 1, got {}", population.density));
+        }
+        if population.min_player_distance < 0 {
+            return Err(format!("population.min_player_distance can not be negative, got {}", population.min_player_distance));
+        }
+        for (i, name) in population.templates.iter().enumerate() {
+            match templates.get(name) {
+                Err(_) => return Err(format!("population.templates[{}] {} is not one of the creatures", i, name)),
+                Ok(t) if t.ai == AiProfile::Player =>
+                    return Err(format!("population.templates[{}] {} can not have the player ai", i, name)),
+                Ok(_) => (),
+            }
+        }
+        Ok(())
+    }
+
+    // only valid configs have templates, see validate
+    pub fn templates(&self) -> Result<Templates, String> {
+        Templates::new(&self.creatures)
+    }
+}
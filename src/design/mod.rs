@@ -1 +1,3 @@
-pub mod blueprint;
\ No newline at end of file
+pub mod blueprint;
+pub mod config;
+pub mod templates;
@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+use serde_json;
+use state::object::{Idx, Color, Icon, Pixel};
+use state::component::{Bundle, Components, Health, Inventory, Render};
+use state::stats::{Combat, Dice};
+use state::level::{Entity, Level};
+use objects::player::Player;
+use objects::character::Character;
+use utils::ipoint::IPoint;
+use error::{GameError, GameResult};
+
+// the templates every game starts with, see GameConfig::creatures
+const BUILTIN: &str = include_str!("creatures.json");
+
+// what drives an entity made from a template
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AiProfile {
+    // controlled from outside through commands
+    Player,
+    // wanders around and chases the closest hostile creature it sees
+    Hunter,
+    // stays put until it sees a hostile creature
+    Guard,
+}
+
+impl Default for AiProfile {
+    fn default() -> AiProfile {
+        AiProfile::Hunter
+    }
+}
+
+// a kind of creature designers can add without touching the code; missing fields
+// take the values of the default, misspelled ones are rejected
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CreatureTemplate {
+    // also the name of the entities made from it
+    pub name: String,
+    pub icon: Icon,
+    pub color: Color,
+    pub sight_range: f32,
+    pub walk_speed: i64,
+    pub attack_speed: i64,
+    pub wait_speed: i64,
+    pub health: i32,
+    // see Combat
+    pub attack: i32,
+    pub defense: i32,
+    pub accuracy: i32,
+    pub evasion: i32,
+    pub critical: i32,
+    pub damage: Dice,
+    pub faction: String,
+    pub ai: AiProfile,
+    // what placing one costs of a level's population budget
+    pub difficulty: u32,
+}
+
+impl Default for CreatureTemplate {
+    fn default() -> CreatureTemplate {
+        CreatureTemplate {
+            name: String::new(),
+            icon: Icon::Enemy,
+            color: Color(255, 255, 255),
+            sight_range: 8.0,
+            walk_speed: 10,
+            attack_speed: 10,
+            wait_speed: 10,
+            health: 10,
+            attack: 0,
+            defense: 0,
+            accuracy: 100,
+            evasion: 0,
+            critical: 0,
+            damage: Dice::flat(1),
+            faction: "monsters".to_string(),
+            ai: AiProfile::Hunter,
+            difficulty: 1,
+        }
+    }
+}
+
+impl CreatureTemplate {
+    // the message names the field, callers say which template it is
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("name can not be empty".to_string());
+        }
+        if !(self.sight_range >= 0.0) {
+            return Err(format!("sight_range can not be negative, got {}", self.sight_range));
+        }
+        let speeds = [("walk_speed", self.walk_speed), ("attack_speed", self.attack_speed), ("wait_speed", self.wait_speed)];
+        for &(field, speed) in speeds.iter() {
+            if speed <= 0 {
+                return Err(format!("{} must be positive, got {}", field, speed));
+            }
+        }
+        if self.health <= 0 {
+            return Err(format!("health must be positive, got {}", self.health));
+        }
+        let chances = [("accuracy", self.accuracy), ("evasion", self.evasion), ("critical", self.critical)];
+        for &(field, chance) in chances.iter() {
+            if chance < 0 {
+                return Err(format!("{} can not be negative, got {}", field, chance));
+            }
+        }
+        if self.critical > 100 {
+            return Err(format!("critical can not be over 100, got {}", self.critical));
+        }
+        self.damage.validate().map_err(|message| format!("damage {}", message))?;
+        if self.damage.min() < 0 {
+            return Err(format!("damage can not roll below 0, got {}", self.damage));
+        }
+        if self.difficulty == 0 {
+            return Err("difficulty must be positive".to_string());
+        }
+        if self.faction.is_empty() {
+            return Err("faction can not be empty".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn components(&self) -> Components {
+        Components {
+            render: Some(Render { pixel: Pixel(self.icon, self.color), ordinal: 0, environment: false }),
+            blocker: true,
+            health: Some(Health::new(self.health)),
+            inventory: if self.ai == AiProfile::Player { Some(Inventory::default()) } else { None },
+            combat: Some(Combat {
+                attack: self.attack,
+                defense: self.defense,
+                accuracy: self.accuracy,
+                evasion: self.evasion,
+                critical: self.critical,
+                damage: self.damage,
+            }),
+            faction: Some(self.faction.clone()),
+            ..Components::named(&self.name)
+        }
+    }
+
+    pub fn build(&self, idx: Idx) -> Bundle {
+        let mut bundle = match self.ai {
+            AiProfile::Player => Bundle::from(Player::from_template(idx, self)),
+            _ => Bundle::from(Character::from_template(idx, self)),
+        };
+        bundle.components = self.components();
+        bundle
+    }
+}
+
+// the creature templates of a game by name
+#[derive(Clone, Debug)]
+pub struct Templates {
+    creatures: BTreeMap<String, CreatureTemplate>,
+}
+
+impl Default for Templates {
+    fn default() -> Templates {
+        Templates::from_json(BUILTIN).expect("builtin creature templates are valid")
+    }
+}
+
+impl Templates {
+    pub fn builtin() -> Vec<CreatureTemplate> {
+        serde_json::from_str(BUILTIN).expect("builtin creature templates are valid")
+    }
+
+    pub fn new(creatures: &[CreatureTemplate]) -> Result<Templates, String> {
+        let mut templates = Templates { creatures: BTreeMap::new() };
+        for (i, creature) in creatures.iter().enumerate() {
+            creature.validate().map_err(|message| format!("creatures[{}].{}", i, message))?;
+            if templates.creatures.insert(creature.name.clone(), creature.clone()).is_some() {
+                return Err(format!("creatures[{}].name {} is used twice", i, creature.name));
+            }
+        }
+        Ok(templates)
+    }
+
+    // a json array of templates
+    pub fn from_json(json: &str) -> GameResult<Templates> {
+        let creatures: Vec<CreatureTemplate> = serde_json::from_str(json)
+            .map_err(|e| GameError::InvalidTemplate { message: e.to_string() })?;
+        Templates::new(&creatures).map_err(|message| GameError::InvalidTemplate { message })
+    }
+
+    pub fn get(&self, name: &str) -> GameResult<&CreatureTemplate> {
+        self.creatures.get(name)
+            .ok_or(GameError::InvalidTemplate { message: format!("no creature template named {}", name) })
+    }
+
+    // in name order
+    pub fn names(&self) -> Vec<&str> {
+        self.creatures.keys().map(|name| name.as_str()).collect()
+    }
+
+    pub fn build(&self, name: &str, idx: Idx) -> GameResult<Bundle> {
+        Ok(self.get(name)?.build(idx))
+    }
+
+    pub fn spawn<'a>(&self, name: &str, level: &'a mut Level, idx: Idx, position: IPoint) -> GameResult<&'a mut Entity> {
+        let bundle = self.build(name, idx)?;
+        level.add_entity(bundle, position)
+    }
+}
@@ -0,0 +1,109 @@
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt;
+use state::object::Idx;
+use utils::ipoint::IPoint;
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind")]
+pub enum GameError {
+    NullPointer { argument: String },
+    InvalidUtf8 { argument: String },
+    InvalidString { message: String },
+    InvalidJson { message: String },
+    InvalidConfig { message: String },
+    InvalidSave { message: String },
+    MissingEntity { idx: Idx },
+    MissingLevel { idx: Idx },
+    DuplicateEntity { idx: Idx },
+    DuplicateLevel { idx: Idx },
+    NotAPlayer { idx: Idx },
+    OutsideLevel { level: Idx, position: IPoint },
+    Stalled,
+    ReplayDiverged { step: usize, expected: String, found: String },
+    NotRecording,
+    GameOver,
+    NotSingleStep,
+    InvalidTimer { message: String },
+    InvalidTemplate { message: String },
+}
+
+pub type GameResult<T> = Result<T, GameError>;
+
+impl GameError {
+    // status code returned by the exports, 0 is reserved for success
+    pub fn code(&self) -> i32 {
+        match self {
+            GameError::NullPointer { .. } => -1,
+            GameError::InvalidUtf8 { .. } => -2,
+            GameError::InvalidString { .. } => -3,
+            GameError::InvalidJson { .. } => -4,
+            GameError::InvalidConfig { .. } => -5,
+            GameError::InvalidSave { .. } => -6,
+            GameError::MissingEntity { .. } => -7,
+            GameError::MissingLevel { .. } => -8,
+            GameError::DuplicateEntity { .. } => -9,
+            GameError::DuplicateLevel { .. } => -10,
+            GameError::NotAPlayer { .. } => -11,
+            GameError::OutsideLevel { .. } => -12,
+            GameError::Stalled => -13,
+            GameError::ReplayDiverged { .. } => -14,
+            GameError::NotRecording => -15,
+            GameError::GameOver => -16,
+            GameError::NotSingleStep => -17,
+            GameError::InvalidTimer { .. } => -18,
+            GameError::InvalidTemplate { .. } => -19,
+        }
+    }
+}
+
+impl Display for GameError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            GameError::NullPointer { argument } => write!(f, "{} is a null pointer", argument),
+            GameError::InvalidUtf8 { argument } => write!(f, "{} is not valid utf-8", argument),
+            GameError::InvalidString { message } => write!(f, "can not pass string: {}", message),
+            GameError::InvalidJson { message } => write!(f, "invalid json: {}", message),
+            GameError::InvalidConfig { message } => write!(f, "invalid config: {}", message),
+            GameError::InvalidSave { message } => write!(f, "invalid save: {}", message),
+            GameError::MissingEntity { idx } => write!(f, "entity {} does not exist", idx),
+            GameError::MissingLevel { idx } => write!(f, "level {} does not exist", idx),
+            GameError::DuplicateEntity { idx } => write!(f, "entity {} already exists", idx),
+            GameError::DuplicateLevel { idx } => write!(f, "level {} already exists", idx),
+            GameError::NotAPlayer { idx } => write!(f, "entity {} is not a player", idx),
+            GameError::OutsideLevel { level, position } => write!(f, "{} is outside level {}", position, level),
+            GameError::Stalled => write!(f, "no entity is able to act"),
+            GameError::ReplayDiverged { step, expected, found } =>
+                write!(f, "replay diverged at step {}: expected state {}, found {}", step, expected, found),
+            GameError::NotRecording => write!(f, "the game is not being recorded"),
+            GameError::GameOver => write!(f, "the game is over, restart it to play again"),
+            GameError::NotSingleStep => write!(f, "run, rest and repeat take more than one action"),
+            GameError::InvalidTimer { message } => write!(f, "invalid timer: {}", message),
+            GameError::InvalidTemplate { message } => write!(f, "invalid creature template: {}", message),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ErrorReport {
+    pub code: i32,
+    pub message: String,
+    pub error: GameError,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<GameError>> = RefCell::new(None);
+}
+
+pub fn set_last_error(error: GameError) {
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(error));
+}
+
+pub fn take_last_error() -> Option<ErrorReport> {
+    LAST_ERROR.with(|e| e.borrow_mut().take()).map(|error| ErrorReport {
+        code: error.code(),
+        message: error.to_string(),
+        error,
+    })
+}
@@ -2,47 +2,513 @@ use utils::ipoint::IPoint;
 use state::world::World;
 use design::blueprint::Blueprint;
 use player::player::PlayerData;
+use player::player::View;
+use player::command::Command;
+use std::collections::HashMap;
 use state::object::Pixel;
 use state::object::Object;
+use state::level::Level;
 use serde_json;
-use objects::player::Player;
-use print_raw;
-use print;
+use state::save::SAVE_VERSION;
+use design::config::GameConfig;
+use error::{GameError, GameResult};
+use serde::Serialize;
+use serde_json::Value;
+use state::object::Idx;
+use replay::{Input, Recording, Step, format_hash};
+use player::lifecycle::{DeathCause, GameStatus};
+use state::context::Effects;
+use state::event::{Event, StopReason};
+use state::timer::WorldEvent;
+use state::component::Bundle;
+use state::save::ObjectData;
+use objects::wall::wall;
+use objects::floor::floor;
+use state::faction::{Factions, Relation};
 
+// longest run or rest, and most steps a repeat takes
+const MAX_STEPS: u32 = 100;
 
+
+#[derive(Serialize, Deserialize)]
 pub struct GameState {
     pub game: World,
-    pub player: PlayerData
+    // every player controlled from outside, each with its own memory and logs
+    pub players: Vec<PlayerData>,
+    // slot in players whose input the scheduler waits for
+    #[serde(default)]
+    current: usize,
+    #[serde(default)]
+    pub status: GameStatus,
+    #[serde(default)]
+    pub config: GameConfig,
+    // last view sent to each client of get_view_diff
+    #[serde(skip)]
+    clients: HashMap<i32, View>,
+    #[serde(skip)]
+    recording: Option<Recording>,
+    // snapshots before each of the last player turns, oldest first, and the turns undone since
+    #[serde(skip)]
+    history: Vec<String>,
+    #[serde(skip)]
+    future: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct SaveRef<'a> {
+    version: u32,
+    state: &'a GameState,
+}
+
+#[derive(Deserialize)]
+struct SaveFile {
+    version: u32,
+    state: Value,
+}
+
+fn to_json<T: Serialize + ?Sized>(value: &T) -> GameResult<String> {
+    serde_json::to_string(value).map_err(|e| GameError::InvalidJson { message: e.to_string() })
+}
+
+// first free tile in row order, so placement does not depend on hash order
+fn free_position(level: &Level) -> GameResult<IPoint> {
+    let position = level.size().zrange().iter().find(|p|
+        !level.is_blocking(*p)
+    );
+    position.ok_or(GameError::InvalidConfig {
+        message: "level has no free tile for a player".to_string()
+    })
+}
+
+// moves up to count snapshots between the stacks, starting with the current one,
+// returns the snapshot to restore
+fn shift(current: String, from: &mut Vec<String>, to: &mut Vec<String>, count: usize) -> String {
+    let mut snapshot = current;
+    for _ in 0..count {
+        match from.pop() {
+            None => break,
+            Some(s) => to.push(::std::mem::replace(&mut snapshot, s)),
+        }
+    }
+    snapshot
+}
+
+// version 1 saves had a single player
+fn migrate_v1(state: &mut Value) {
+    if let Some(fields) = state.as_object_mut() {
+        if let Some(player) = fields.remove("player") {
+            fields.insert("players".to_string(), Value::Array(vec![player]));
+        }
+    }
+}
+
+// version 2 entities were a single object, now they are components with an optional ai
+fn migrate_v2(state: &mut Value) -> GameResult<()> {
+    let levels = match state.pointer_mut("/game/levels").and_then(|l| l.as_object_mut()) {
+        None => return Ok(()),
+        Some(levels) => levels,
+    };
+    for (_, level) in levels.iter_mut() {
+        let tiles = match level.get_mut("tiles").and_then(|t| t.as_array_mut()) {
+            None => continue,
+            Some(tiles) => tiles,
+        };
+        for tile in tiles.iter_mut() {
+            if let Some(entities) = tile.get_mut(1).and_then(|e| e.as_array_mut()) {
+                for entity in entities.iter_mut() {
+                    migrate_entity(entity)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn migrate_entity(entity: &mut Value) -> GameResult<()> {
+    let invalid = |message: String| GameError::InvalidSave { message };
+    let fields = match entity.as_object_mut() {
+        None => return Ok(()),
+        Some(fields) => fields,
+    };
+    let object = match fields.remove("object") {
+        None => return Ok(()),
+        Some(object) => object,
+    };
+    let (kind, data) = object.as_object().and_then(|o| o.iter().next())
+        .map(|(kind, data)| (kind.clone(), data.clone()))
+        .ok_or(invalid(format!("entity without an object: {}", object)))?;
+    let idx = data.get("idx").and_then(|i| i.as_u64())
+        .ok_or(invalid(format!("{} without an idx", kind)))? as Idx;
+    let mut bundle = match kind.as_str() {
+        "Wall" => wall(idx),
+        "Floor" => floor(idx),
+        _ => match serde_json::from_value(object.clone()).map_err(|e| invalid(e.to_string()))? {
+            ObjectData::Player(player) => Bundle::from(player),
+            ObjectData::Character(character) => Bundle::from(character),
+        },
+    };
+    if let Some(health) = bundle.components.health.as_mut() {
+        health.max_health = data.get("max_health").and_then(|h| h.as_i64()).unwrap_or(10) as i32;
+        health.health = data.get("health").and_then(|h| h.as_i64()).unwrap_or(health.max_health as i64) as i32;
+    }
+    let json = |e: serde_json::Result<Value>| e.map_err(|e| invalid(e.to_string()));
+    fields.insert("idx".to_string(), Value::from(idx));
+    fields.insert("components".to_string(), json(serde_json::to_value(&bundle.components))?);
+    fields.insert("ai".to_string(), json(serde_json::to_value(&bundle.ai))?);
+    Ok(())
 }
 
 impl GameState {
-    pub fn new(size: IPoint) -> GameState {
-        let mut game = World::new();
+    pub fn new(size: IPoint) -> GameResult<GameState> {
+        GameState::from_config(GameConfig::with_size(size))
+    }
+    pub fn from_config(config: GameConfig) -> GameResult<GameState> {
+        config.validate().map_err(|message| GameError::InvalidConfig { message })?;
+        let mut game = World::new(config.world_seed);
+        let templates = config.templates().map_err(|message| GameError::InvalidConfig { message })?;
+        game.set_templates(templates.clone());
+        game.set_factions(Factions::new(&config.relations).map_err(|message| GameError::InvalidConfig { message })?);
         let idx = game.next_id();
 
-        let level = Blueprint::example(size).level_from_blueprint(&mut game);
+        let mut blueprint = Blueprint::from_config(&config);
+        let level = blueprint.level_from_blueprint(&mut game)?;
+        level.set_policy(config.simulation);
+        let level_idx = level.idx();
 
-        let empty_tile = level.tiles().iter().filter(|(_p, tile)|
-            !tile.iter().any(|e| e.object().is_blocking())
-        ).next();
-        let empty_pos = match empty_tile {
-            None => panic!(),
-            Some(p) => p.0
-        };
-        level.add_entity(Box::new(Player::new(idx)), *empty_pos);
+        let position = free_position(level)?;
+        templates.spawn(&config.player_template, level, idx, position)?;
+        let monsters = blueprint.populate(&mut game, level_idx, &config.population, position)?;
+        info!("created world with seed {:?}, player {} at {}, {} monsters", config.world_seed, idx, position, monsters.len());
+        if config.wandering_monsters > 0 {
+            let every = config.wandering_monsters;
+            game.schedule_event(every, Some(every), WorldEvent::SpawnMonster {
+                level: level_idx,
+                template: config.wandering_template.clone(),
+            })?;
+        }
 
-        GameState {
+        Ok(GameState {
             game,
-            player: PlayerData::new(idx)
+            players: vec![PlayerData::new(idx)],
+            current: 0,
+            status: GameStatus::Playing,
+            config,
+            clients: HashMap::new(),
+            recording: None,
+            history: Vec::new(),
+            future: Vec::new(),
+        })
+    }
+    // spawns another player on the level of the first one, returns its slot
+    pub fn add_player(&mut self) -> GameResult<usize> {
+        self.check_playing()?;
+        let first = self.players.first().map(|p| p.player).ok_or(GameError::Stalled)?;
+        let idx = self.game.next_id();
+        let templates = self.game.templates().clone();
+        let level = self.game.get_mut_entity_level(first).ok_or(GameError::MissingEntity { idx: first })?;
+        let position = free_position(level)?;
+        templates.spawn(&self.config.player_template, level, idx, position)?;
+        info!("added player {} at {}", idx, position);
+
+        self.players.push(PlayerData::new(idx));
+        self.advance()?;
+        self.record(Input::AddPlayer)?;
+        Ok(self.players.len() - 1)
+    }
+    // replays only reach the same state when recording starts right after from_config
+    pub fn start_recording(&mut self) -> GameResult<()> {
+        self.recording = Some(Recording {
+            config: self.config.clone(),
+            start: format_hash(self.game.state_hash()?),
+            steps: Vec::new(),
+        });
+        Ok(())
+    }
+    pub fn recording(&self) -> Option<&Recording> {
+        self.recording.as_ref()
+    }
+    pub fn stop_recording(&mut self) -> Option<Recording> {
+        self.recording.take()
+    }
+    fn record(&mut self, input: Input) -> GameResult<()> {
+        if self.recording.is_none() {
+            return Ok(());
+        }
+        let hash = format_hash(self.game.state_hash()?);
+        if let Some(recording) = self.recording.as_mut() {
+            recording.steps.push(Step { input, hash });
+        }
+        Ok(())
+    }
+    pub fn turn_owner(&self) -> usize {
+        self.current
+    }
+    pub fn owner(&self) -> &PlayerData {
+        &self.players[self.current]
+    }
+    fn slot(&self, idx: Idx) -> Option<usize> {
+        self.players.iter().position(|p| p.player == idx)
+    }
+    fn check_playing(&self) -> GameResult<()> {
+        match self.status {
+            GameStatus::Playing => Ok(()),
+            _ => Err(GameError::GameOver),
+        }
+    }
+    // lets other entities act until one of the players is ready, and makes it the turn owner;
+    // stops early once the game is over, as dead players never become ready again
+    fn advance(&mut self) -> GameResult<()> {
+        while self.status == GameStatus::Playing {
+            let next = self.game.lapse_time().map(|e| e.idx());
+            for effects in self.game.take_fired() {
+                for player in self.players.iter_mut() {
+                    player.record(&self.game, &effects)?;
+                }
+            }
+            // a player who dies while time passes may leave nobody to act
+            self.note_deaths(None);
+            let idx = match next {
+                Err(GameError::Stalled) if self.status != GameStatus::Playing => return Ok(()),
+                next => next?,
+            };
+            if let Some(slot) = self.slot(idx) {
+                self.current = slot;
+                return Ok(());
+            }
+            let effects = self.game.invoke_actions(idx)?;
+            for player in self.players.iter_mut() {
+                player.record(&self.game, &effects)?;
+            }
+            self.note_deaths(Some(&effects));
+        }
+        Ok(())
+    }
+    // players who lost all health, with the attack from the effects or the poison that killed them if any
+    fn note_deaths(&mut self, effects: Option<&Effects>) {
+        let poisoned = self.game.take_poisoned();
+        for slot in 0..self.players.len() {
+            let idx = self.players[slot].player;
+            let health = self.game.get_entity(idx).and_then(|e| e.components().health).map(|h| h.health);
+            if self.players[slot].death.is_some() || health.map_or(true, |h| h > 0) {
+                continue;
+            }
+            let attacker = effects.and_then(|e| e.events.iter().rev().filter_map(|event| match event {
+                Event::Attacked { source, target } if *target == idx => Some(*source),
+                _ => None,
+            }).next());
+            let cause = match attacker {
+                None if poisoned.contains(&idx) => DeathCause::Poisoned,
+                None => DeathCause::Unknown,
+                Some(by) => DeathCause::Killed {
+                    by,
+                    name: self.game.get_entity(by).map_or("something", |e| e.name()).to_string(),
+                },
+            };
+            let message = cause.message(self.players[slot].turn);
+            info!("player {} died: {}", idx, message);
+            self.players[slot].messages.push(message.clone());
+            self.players[slot].death = Some(cause.clone());
+            if self.players.iter().all(|p| p.death.is_some()) {
+                self.status = GameStatus::Dead { cause, message };
+            }
+        }
+    }
+    // won once every other creature there was has died
+    fn check_victory(&mut self) {
+        if self.status != GameStatus::Playing {
+            return;
+        }
+        // neutrals and allies do not have to die for the players to win
+        let players: Vec<Idx> = self.players.iter().filter(|p| p.death.is_none()).map(|p| p.player).collect();
+        let health: Vec<i32> = self.game.active_entities().into_iter()
+            .filter(|idx| self.slot(*idx).is_none())
+            .filter(|idx| players.iter().any(|p| self.game.relation(*p, *idx) == Relation::Hostile))
+            .filter_map(|idx| self.game.get_entity(idx))
+            .filter_map(|e| e.components().health)
+            .map(|h| h.health)
+            .collect();
+        if !health.is_empty() && health.iter().all(|h| *h <= 0) {
+            self.status = GameStatus::Won;
+        }
+    }
+    pub fn get_game_status(&self) -> GameResult<String> {
+        to_json(&self.status)
+    }
+    // a fresh world from the same config, in place so the pointer handed out stays valid
+    pub fn restart(&mut self) -> GameResult<()> {
+        let recording = self.recording.take();
+        *self = GameState::from_config(self.config.clone())?;
+        self.recording = recording;
+        self.record(Input::Restart)
+    }
+    pub fn process_key(&mut self, string: &str) -> GameResult<()> {
+        debug!("press key {:?}", string);
+        self.check_playing()?;
+        match Command::from_key(string) {
+            None => self.advance()?,
+            Some(command) => { self.run_command(command)?; }
+        }
+        self.record(Input::Key { key: string.to_string() })
+    }
+    // the command is for the turn owner, returns false when its action failed and took no time
+    pub fn submit_command(&mut self, command: Command) -> GameResult<bool> {
+        let accepted = self.run_command(command)?;
+        self.record(Input::Command { command })?;
+        Ok(accepted)
+    }
+    fn run_command(&mut self, command: Command) -> GameResult<bool> {
+        self.check_playing()?;
+        self.advance()?;
+        self.check_playing()?;
+        match command {
+            Command::Run { .. } | Command::Rest => {
+                let step = command.step().ok_or(GameError::NotSingleStep)?;
+                self.repeat(step, MAX_STEPS, command)
+            }
+            Command::Repeat { count } => match self.owner().last_command {
+                Some(step) => self.repeat(step, count.min(MAX_STEPS), command),
+                None => {
+                    self.players[self.current].messages.push("There is nothing to repeat.".to_string());
+                    Ok(false)
+                }
+            },
+            _ => {
+                let snapshot = self.snapshot()?;
+                self.take_turn(command, snapshot)
+            }
         }
     }
-    pub fn process_key(&mut self, string: &str) {
-        //print("press key".to_string());
-        self.player.process_key(&mut self.game, string);
+    fn snapshot(&self) -> GameResult<Option<String>> {
+        Ok(if self.config.undo_limit > 0 { Some(self.save()?) } else { None })
     }
-    pub fn get_view(&mut self) -> String {
+    // one single step command for the turn owner, the snapshot is pushed to the undo history if it works
+    fn take_turn(&mut self, command: Command, snapshot: Option<String>) -> GameResult<bool> {
+        let slot = self.current;
+        let idx = self.players[slot].player;
+        self.players[slot].set_command(&mut self.game, command)?;
+        let effects = self.game.invoke_actions(idx)?;
+        for player in self.players.iter_mut() {
+            player.record(&self.game, &effects)?;
+        }
+        // failed actions cost nothing, the same player is asked again,
+        // unless they died trying, like walking into an attack of opportunity
+        if let Some(outcome) = effects.outcome.as_ref().filter(|o| !o.is_success()) {
+            self.players[slot].report(outcome);
+            self.note_deaths(Some(&effects));
+            self.check_victory();
+            return Ok(false);
+        }
+
+        if let Some(snapshot) = snapshot {
+            self.history.push(snapshot);
+            if self.history.len() > self.config.undo_limit {
+                self.history.remove(0);
+            }
+            self.future.clear();
+        }
+        self.players[slot].last_command = Some(command);
+        self.players[slot].turn += 1;
+        self.note_deaths(Some(&effects));
+        self.advance()?;
+        self.check_victory();
+        for player in self.players.iter_mut() {
+            player.update_memory(&self.game)?;
+        }
+        Ok(true)
+    }
+    // takes the step up to count times for the turn owner, stopping early on anything the player
+    // should look at; the whole run is a single undo step. Returns false when no step was taken.
+    fn repeat(&mut self, step: Command, count: u32, mode: Command) -> GameResult<bool> {
+        let slot = self.current;
+        let idx = self.players[slot].player;
+        let run = match mode {
+            Command::Run { dir } => Some(dir),
+            _ => None,
+        };
+        let mut snapshot = self.snapshot()?;
+        let mut seen = self.visible_enemies(slot)?;
+        let mut sides = match run {
+            Some(dir) => Some(self.game.open_sides(idx, dir)?),
+            None => None,
+        };
+        let mut taken = 0;
+        let reason = loop {
+            if taken == count {
+                break StopReason::Completed;
+            }
+            let stats = self.game.get_entity(idx).and_then(|e| e.components().health)
+                .ok_or(GameError::MissingEntity { idx })?;
+            if mode == Command::Rest && stats.health >= stats.max_health {
+                break StopReason::Healed;
+            }
+            if !self.take_turn(step, snapshot.take())? {
+                break StopReason::Failed;
+            }
+            taken += 1;
+            if self.status != GameStatus::Playing {
+                break StopReason::GameOver;
+            }
+            if self.current != slot {
+                break StopReason::TurnPassed;
+            }
+            let health = self.game.get_entity(idx).and_then(|e| e.components().health).map_or(0, |h| h.health);
+            if health < stats.health {
+                break StopReason::Damaged { amount: stats.health - health };
+            }
+            let enemies = self.visible_enemies(slot)?;
+            if let Some(enemy) = enemies.iter().find(|e| !seen.contains(e)) {
+                break StopReason::EnemyInSight { enemy: *enemy };
+            }
+            seen = enemies;
+            if let Some(dir) = run {
+                let now = self.game.open_sides(idx, dir)?;
+                if sides.replace(now) != Some(now) {
+                    break StopReason::Junction;
+                }
+            }
+        };
+        debug!("player {} stopped after {} steps: {:?}", idx, taken, reason);
+        let time = self.game.time();
+        self.players[slot].stop(time, reason);
+        Ok(taken > 0)
+    }
+    // creatures in the player's sight hostile to them
+    fn visible_enemies(&self, slot: usize) -> GameResult<Vec<Idx>> {
+        let player = self.players[slot].player;
+        let creatures = self.players[slot].visible_creatures(&self.game)?;
+        Ok(creatures.into_iter().filter(|idx| self.game.relation(player, *idx) == Relation::Hostile).collect())
+    }
+    // steps back over the last player turns, returns how many were undone
+    pub fn undo(&mut self, count: usize) -> GameResult<usize> {
+        let count = count.min(self.history.len());
+        if count > 0 {
+            let snapshot = shift(self.save()?, &mut self.history, &mut self.future, count);
+            self.restore(&snapshot)?;
+        }
+        self.record(Input::Undo { count })?;
+        Ok(count)
+    }
+    // plays undone turns again, until the next command clears them
+    pub fn redo(&mut self, count: usize) -> GameResult<usize> {
+        let count = count.min(self.future.len());
+        if count > 0 {
+            let snapshot = shift(self.save()?, &mut self.future, &mut self.history, count);
+            self.restore(&snapshot)?;
+        }
+        self.record(Input::Redo { count })?;
+        Ok(count)
+    }
+    fn restore(&mut self, snapshot: &str) -> GameResult<()> {
+        let state = GameState::load(snapshot)?;
+        self.game = state.game;
+        self.players = state.players;
+        self.current = state.current;
+        self.status = state.status;
+        Ok(())
+    }
+    pub fn get_view(&mut self) -> GameResult<String> {
         let empty = &Pixel::empty();
-        let view = self.player.build_view(&self.game);
+        let view = self.owner().build_view(&self.game)?;
         let mut result = Vec::new();
         for y in 0..view.size.y {
             let mut row = Vec::new();
@@ -51,12 +517,63 @@ impl GameState {
             }
             result.push(row);
         }
-        serde_json::to_string(&result).unwrap()
+        to_json(&result)
+    }
+    pub fn get_view_diff(&mut self, client: i32, full: bool) -> GameResult<String> {
+        let view = self.owner().build_view(&self.game)?;
+        let diff = if full {
+            view.diff(None)
+        } else {
+            view.diff(self.clients.get(&client))
+        };
+        let result = to_json(&diff);
+        self.clients.insert(client, view);
+        result
+    }
+    pub fn inspect(&self, position: IPoint) -> GameResult<String> {
+        to_json(&self.owner().inspect(&self.game, position)?)
+    }
+    // events newer than the given id, -1 for all that are kept
+    pub fn get_events(&self, since: i64) -> GameResult<String> {
+        to_json(&self.owner().get_events(since))
+    }
+    pub fn get_player_status(&self) -> GameResult<String> {
+        to_json(&self.owner().status(&self.game)?)
     }
-    pub fn get_messages(&mut self, count: i32) -> String {
-        let messages = self.player.get_messages();
-        let start_idx = (messages.len() as i32 - count).max(0) as usize;
+    // the last count messages, none for negative counts
+    pub fn get_messages(&mut self, count: i32) -> GameResult<String> {
+        let messages = self.owner().get_messages();
+        let start_idx = messages.len().saturating_sub(count.max(0) as usize);
         let part = &messages[start_idx..];
-        serde_json::to_string(part).unwrap()
+        to_json(part)
+    }
+    pub fn save(&self) -> GameResult<String> {
+        to_json(&SaveRef { version: SAVE_VERSION, state: self })
+    }
+    pub fn load(json: &str) -> GameResult<GameState> {
+        let file: SaveFile = serde_json::from_str(json)
+            .map_err(|e| GameError::InvalidSave { message: e.to_string() })?;
+        if file.version > SAVE_VERSION {
+            return Err(GameError::InvalidSave {
+                message: format!("save version {} is newer than supported {}", file.version, SAVE_VERSION)
+            });
+        }
+        let mut state = file.state;
+        if file.version < 2 {
+            migrate_v1(&mut state);
+        }
+        if file.version < 3 {
+            migrate_v2(&mut state)?;
+        }
+        let mut state: GameState = serde_json::from_value(state)
+            .map_err(|e| GameError::InvalidSave { message: e.to_string() })?;
+        let templates = state.config.templates().map_err(|message| GameError::InvalidSave { message })?;
+        state.game.set_templates(templates);
+        if state.current >= state.players.len() {
+            return Err(GameError::InvalidSave {
+                message: format!("turn owner {} is not one of {} players", state.current, state.players.len())
+            });
+        }
+        Ok(state)
     }
 }
\ No newline at end of file
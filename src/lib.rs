@@ -7,77 +7,285 @@ extern crate serde_derive;
 extern crate serde;
 extern crate serde_json;
 
+#[macro_use]
+pub mod logging;
 #[cfg(test)]
 mod tests;
-mod design;
-mod logic;
-mod player;
-mod state;
-mod utils;
-mod foundation;
-mod objects;
+pub mod design;
+pub mod logic;
+pub mod player;
+pub mod state;
+pub mod utils;
+pub mod foundation;
+pub mod objects;
+pub mod error;
+pub mod replay;
 
 use std::ffi::CString;
 use std::ffi::CStr;
 use std::os::raw::c_char;
 use std::boxed::Box;
+use std::ptr;
 use foundation::GameState;
+use design::config::GameConfig;
+use player::command::Command;
+use error::{GameError, GameResult, set_last_error, take_last_error};
+use logging::LogLevel;
 use utils::ipoint::IPoint;
+use replay::Recording;
+
+// exports never panic on bad input: functions returning a pointer return null on failure,
+// the others return a status code (0 on success, GameError::code otherwise),
+// in both cases details are available through get_last_error
+
+fn game_ref<'a>(pimpl: *mut GameState) -> GameResult<&'a mut GameState> {
+    // unsafe = borrow raw pointer, the caller keeps ownership
+    unsafe { pimpl.as_mut() }.ok_or(GameError::NullPointer { argument: "pimpl".to_string() })
+}
+
+fn read_str<'a>(raw_string: *mut c_char, argument: &str) -> GameResult<&'a str> {
+    if raw_string.is_null() {
+        return Err(GameError::NullPointer { argument: argument.to_string() });
+    }
+    // unsafe = borrow raw pointer, the caller keeps ownership
+    unsafe { CStr::from_ptr(raw_string) }.to_str()
+        .map_err(|_| GameError::InvalidUtf8 { argument: argument.to_string() })
+}
+
+fn report(error: GameError) {
+    warn!("{}", error);
+    set_last_error(error);
+}
+
+fn status(result: GameResult<()>) -> i32 {
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            let code = e.code();
+            report(e);
+            code
+        }
+    }
+}
+
+fn string_or_null(result: GameResult<String>) -> *mut c_char {
+    let string = result.and_then(|s| CString::new(s)
+        .map_err(|e| GameError::InvalidString { message: e.to_string() }));
+    match string {
+        // forget the string, so resources are not disposed
+        Ok(s) => s.into_raw(),
+        Err(e) => {
+            report(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+fn game_or_null(result: GameResult<GameState>) -> *mut GameState {
+    match result {
+        // drop pointer without disposing resources
+        Ok(game) => Box::into_raw(Box::new(game)),
+        Err(e) => {
+            report(e);
+            ptr::null_mut()
+        }
+    }
+}
 
 #[no_mangle]
-pub fn press_key(pimpl: *mut GameState, key: *mut c_char) {
-    // unsafe = grab raw pointers
-    let mut game: Box<GameState> = unsafe { Box::from_raw(pimpl) };
-    let input: &str = unsafe { CStr::from_ptr(key) }.to_str().unwrap();
+pub fn press_key(pimpl: *mut GameState, key: *mut c_char) -> i32 {
+    status(game_ref(pimpl).and_then(|game| {
+        let input = read_str(key, "key")?;
+        game.process_key(input)
+    }))
+}
 
-    game.process_key(input);
+// 1 when the action worked, 0 when it failed and took no time (see get_messages), negative status code on errors
+#[no_mangle]
+pub fn submit_command(pimpl: *mut GameState, json: *mut c_char) -> i32 {
+    let accepted = game_ref(pimpl).and_then(|game| {
+        let input = read_str(json, "json")?;
+        let command: Command = serde_json::from_str(input)
+            .map_err(|e| GameError::InvalidJson { message: e.to_string() })?;
+        game.submit_command(command)
+    });
+    match accepted {
+        Ok(a) => a as i32,
+        Err(e) => status(Err(e)),
+    }
+}
+
+// slot of the player whose input is awaited, the other exports act for this player
+#[no_mangle]
+pub fn get_turn_owner(pimpl: *mut GameState) -> i32 {
+    match game_ref(pimpl) {
+        Ok(game) => game.turn_owner() as i32,
+        Err(e) => status(Err(e)),
+    }
+}
+
+// spawns another player for hot-seat play, returns its slot or a negative status code
+#[no_mangle]
+pub fn add_player(pimpl: *mut GameState) -> i32 {
+    match game_ref(pimpl).and_then(|game| game.add_player()) {
+        Ok(slot) => slot as i32,
+        Err(e) => status(Err(e)),
+    }
+}
+
+// steps back over the last n player turns, returns how many were undone;
+// needs undo_limit in the config, otherwise nothing is kept to go back to
+#[no_mangle]
+pub fn undo(pimpl: *mut GameState, n: i32) -> i32 {
+    match game_ref(pimpl).and_then(|game| game.undo(n.max(0) as usize)) {
+        Ok(count) => count as i32,
+        Err(e) => status(Err(e)),
+    }
+}
 
-    // unsafe - drop pointer without disposing resources
-    Box::into_raw(game);
+// plays again up to n undone turns, returns how many
+#[no_mangle]
+pub fn redo(pimpl: *mut GameState, n: i32) -> i32 {
+    match game_ref(pimpl).and_then(|game| game.redo(n.max(0) as usize)) {
+        Ok(count) => count as i32,
+        Err(e) => status(Err(e)),
+    }
 }
 
 #[no_mangle]
 pub fn get_view(pimpl: *mut GameState) -> *mut c_char {
-    // unsafe = grab raw pointer
-    let mut game: Box<GameState> = unsafe { Box::from_raw(pimpl) };
+    string_or_null(game_ref(pimpl).and_then(|game| game.get_view()))
+}
+
+#[no_mangle]
+pub fn get_view_diff(pimpl: *mut GameState, client: i32, full: i32) -> *mut c_char {
+    string_or_null(game_ref(pimpl).and_then(|game| game.get_view_diff(client, full != 0)))
+}
+
+#[no_mangle]
+pub fn inspect(pimpl: *mut GameState, x: i32, y: i32) -> *mut c_char {
+    string_or_null(game_ref(pimpl).and_then(|game| game.inspect(IPoint { x, y })))
+}
 
-    let result = game.get_view();
+#[no_mangle]
+pub fn get_events(pimpl: *mut GameState, since: i32) -> *mut c_char {
+    string_or_null(game_ref(pimpl).and_then(|game| game.get_events(since as i64)))
+}
 
-    // unsafe - drop pointers without disposing resources
-    Box::into_raw(game);
-    CString::new(result).unwrap().into_raw()
+// stats and active status effects of the turn owner, and the world time
+#[no_mangle]
+pub fn get_player_status(pimpl: *mut GameState) -> *mut c_char {
+    string_or_null(game_ref(pimpl).and_then(|game| game.get_player_status()))
+}
+
+// {"state": "playing"}, {"state": "won"} or {"state": "dead", "cause": ..., "message": ...}
+#[no_mangle]
+pub fn get_game_status(pimpl: *mut GameState) -> *mut c_char {
+    string_or_null(game_ref(pimpl).and_then(|game| game.get_game_status()))
+}
+
+// builds a fresh world from the same config behind the same pointer
+#[no_mangle]
+pub fn restart(pimpl: *mut GameState) -> i32 {
+    status(game_ref(pimpl).and_then(|game| game.restart()))
 }
 
 #[no_mangle]
 pub fn get_messages(pimpl: *mut GameState, count: i32) -> *mut c_char {
-    // unsafe = grab raw pointer
-    let mut game: Box<GameState> = unsafe { Box::from_raw(pimpl) };
+    string_or_null(game_ref(pimpl).and_then(|game| game.get_messages(count)))
+}
+
+#[no_mangle]
+pub fn save_game(pimpl: *mut GameState) -> *mut c_char {
+    string_or_null(game_ref(pimpl).and_then(|game| game.save()))
+}
+
+#[no_mangle]
+pub fn load_game(json: *mut c_char) -> *mut GameState {
+    game_or_null(read_str(json, "json").and_then(GameState::load))
+}
 
-    let result = game.get_messages(count);
+// records every input from now on, start right after creating the world to get a replayable recording
+#[no_mangle]
+pub fn start_recording(pimpl: *mut GameState) -> i32 {
+    status(game_ref(pimpl).and_then(|game| game.start_recording()))
+}
 
-    // unsafe - drop pointers without disposing resources
-    Box::into_raw(game);
-    CString::new(result).unwrap().into_raw()
+#[no_mangle]
+pub fn get_recording(pimpl: *mut GameState) -> *mut c_char {
+    string_or_null(game_ref(pimpl).and_then(|game| {
+        let recording = game.recording().ok_or(GameError::NotRecording)?;
+        serde_json::to_string(recording).map_err(|e| GameError::InvalidJson { message: e.to_string() })
+    }))
+}
+
+// plays a recording again, returns null if the game did not reach the recorded states
+#[no_mangle]
+pub fn replay_game(json: *mut c_char) -> *mut GameState {
+    game_or_null(read_str(json, "json").and_then(|input| {
+        let recording: Recording = serde_json::from_str(input)
+            .map_err(|e| GameError::InvalidJson { message: e.to_string() })?;
+        replay::replay(&recording)
+    }))
 }
 
 #[no_mangle]
 pub fn create_world() -> *mut GameState {
-    // unsafe
-    // create World, get pointer to it
-    // drop pointer without disposing resources
-    Box::into_raw(Box::new(GameState::new(IPoint{x: 60, y: 40})))
+    game_or_null(GameState::from_config(GameConfig::default()))
+}
+
+#[no_mangle]
+pub fn create_world_with_config(json: *mut c_char) -> *mut GameState {
+    game_or_null(read_str(json, "json").and_then(|input| {
+        let config: GameConfig = serde_json::from_str(input)
+            .map_err(|e| GameError::InvalidJson { message: e.to_string() })?;
+        GameState::from_config(config)
+    }))
 }
 
 #[no_mangle]
-pub fn destroy_world(pimpl: *mut GameState) {
+pub fn destroy_world(pimpl: *mut GameState) -> i32 {
+    if pimpl.is_null() {
+        return status(Err(GameError::NullPointer { argument: "pimpl".to_string() }));
+    }
     unsafe {
         // grab raw pointer and dispose resources
         Box::from_raw(pimpl);
     };
+    0
+}
+
+// level: 0 trace, 1 debug, 2 info, 3 warn, 4 off;
+// module is a module path prefix such as "autismarchia_back::state::world", or null for the default level
+#[no_mangle]
+pub fn set_log_level(level: i32, module: *mut c_char) -> i32 {
+    status(LogLevel::from_code(level)
+        .ok_or(GameError::InvalidConfig { message: format!("unknown log level {}", level) })
+        .and_then(|level| {
+            if module.is_null() {
+                logging::set_level(level);
+            } else {
+                logging::set_module_level(read_str(module, "module")?, level);
+            }
+            Ok(())
+        }))
+}
+
+#[no_mangle]
+pub fn get_last_error() -> *mut c_char {
+    // null when there was no error since the last call
+    match take_last_error() {
+        None => ptr::null_mut(),
+        Some(report) => string_or_null(serde_json::to_string(&report)
+            .map_err(|e| GameError::InvalidJson { message: e.to_string() })),
+    }
 }
 
 #[no_mangle]
 pub fn deallocate(raw_string: *mut c_char) {
+    if raw_string.is_null() {
+        return;
+    }
     unsafe {
         // grab raw pointer and dispose resources
         CString::from_raw(raw_string);
@@ -91,12 +299,8 @@ pub fn allocate(length: usize) -> *mut c_char {
     CString::new(" ".repeat(length)).unwrap().into_raw()
 }
 
-pub fn print(string: String) {
-    unsafe {
-        print_raw(CString::new(string).unwrap().into_raw())
-    }
-}
-
+// provided by the js host, see logging::WasmSink
+#[cfg(target_arch = "wasm32")]
 extern {
     fn print_raw(raw_string: *mut c_char);
 }
\ No newline at end of file
@@ -0,0 +1,168 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::rc::Rc;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Off,
+}
+
+impl LogLevel {
+    pub fn from_code(code: i32) -> Option<LogLevel> {
+        match code {
+            0 => Some(LogLevel::Trace),
+            1 => Some(LogLevel::Debug),
+            2 => Some(LogLevel::Info),
+            3 => Some(LogLevel::Warn),
+            4 => Some(LogLevel::Off),
+            _ => None,
+        }
+    }
+}
+
+impl Display for LogLevel {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            LogLevel::Trace => "TRACE",
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Info => "INFO",
+            LogLevel::Warn => "WARN",
+            LogLevel::Off => "OFF",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+pub trait LogSink {
+    fn write(&self, level: LogLevel, module: &str, message: &str);
+}
+
+pub fn format_line(level: LogLevel, module: &str, message: &str) -> String {
+    format!("[{} {}] {}", level, module, message)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub struct WasmSink;
+
+#[cfg(target_arch = "wasm32")]
+impl LogSink for WasmSink {
+    fn write(&self, level: LogLevel, module: &str, message: &str) {
+        use std::ffi::CString;
+        // interior nul bytes would make the line unprintable, drop them instead
+        let line = format_line(level, module, message).replace('\0', "");
+        unsafe {
+            ::print_raw(CString::new(line).unwrap().into_raw())
+        }
+    }
+}
+
+pub struct StderrSink;
+
+impl LogSink for StderrSink {
+    fn write(&self, level: LogLevel, module: &str, message: &str) {
+        eprintln!("{}", format_line(level, module, message));
+    }
+}
+
+// keeps lines in memory, clones share the same buffer
+#[derive(Clone, Default)]
+pub struct BufferSink {
+    lines: Rc<RefCell<Vec<String>>>,
+}
+
+impl BufferSink {
+    pub fn new() -> BufferSink {
+        BufferSink::default()
+    }
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.borrow().clone()
+    }
+}
+
+impl LogSink for BufferSink {
+    fn write(&self, level: LogLevel, module: &str, message: &str) {
+        self.lines.borrow_mut().push(format_line(level, module, message));
+    }
+}
+
+struct Logger {
+    sink: Box<LogSink>,
+    level: LogLevel,
+    // module path prefixes with their own levels, the longest matching prefix wins
+    modules: Vec<(String, LogLevel)>,
+}
+
+impl Logger {
+    fn new() -> Logger {
+        #[cfg(target_arch = "wasm32")]
+        let sink: Box<LogSink> = Box::new(WasmSink);
+        #[cfg(not(target_arch = "wasm32"))]
+        let sink: Box<LogSink> = Box::new(StderrSink);
+        Logger { sink, level: LogLevel::Warn, modules: Vec::new() }
+    }
+
+    fn level_for(&self, module: &str) -> LogLevel {
+        self.modules.iter()
+            .filter(|(prefix, _)| module.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.level, |(_, level)| *level)
+    }
+}
+
+thread_local! {
+    static LOGGER: RefCell<Logger> = RefCell::new(Logger::new());
+}
+
+pub fn set_sink(sink: Box<LogSink>) {
+    LOGGER.with(|l| l.borrow_mut().sink = sink);
+}
+
+pub fn set_level(level: LogLevel) {
+    LOGGER.with(|l| l.borrow_mut().level = level);
+}
+
+pub fn set_module_level(module: &str, level: LogLevel) {
+    LOGGER.with(|l| {
+        let mut logger = l.borrow_mut();
+        logger.modules.retain(|(prefix, _)| prefix != module);
+        logger.modules.push((module.to_string(), level));
+    });
+}
+
+pub fn enabled(level: LogLevel, module: &str) -> bool {
+    level != LogLevel::Off && LOGGER.with(|l| level >= l.borrow().level_for(module))
+}
+
+pub fn log(level: LogLevel, module: &str, message: &str) {
+    LOGGER.with(|l| l.borrow().sink.write(level, module, message));
+}
+
+macro_rules! log_at {
+    ($level:expr, $($arg:tt)*) => {
+        if $crate::logging::enabled($level, module_path!()) {
+            $crate::logging::log($level, module_path!(), &format!($($arg)*));
+        }
+    }
+}
+
+macro_rules! trace {
+    ($($arg:tt)*) => { log_at!($crate::logging::LogLevel::Trace, $($arg)*) }
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => { log_at!($crate::logging::LogLevel::Debug, $($arg)*) }
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => { log_at!($crate::logging::LogLevel::Info, $($arg)*) }
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => { log_at!($crate::logging::LogLevel::Warn, $($arg)*) }
+}
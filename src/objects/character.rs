@@ -1,16 +1,24 @@
-use state::object::{Idx, Color, Pixel, Icon, Object};
-use state::level::{Entity, Level};
+use state::object::{Idx, Color, Pixel, Icon, Object, Abilities};
+use state::level::Entity;
 use utils::ipoint::IPoint;
 use utils::point::Point;
 use ordered_float::OrderedFloat;
 use state::context::Context;
 use state::context::Action;
+use state::context::Outcome;
 use rand::Rng;
 use std::cell::Cell;
 use state::context::Effects;
+use state::save::ObjectData;
+use state::event::Event;
+use state::component::{Bundle, Components, Health, Render};
+use state::status::{StatusEffect, StatusEffects, StatusKind};
+use state::stats::Combat;
+use design::templates::{AiProfile, CreatureTemplate};
+use state::faction::Relation;
 
-struct Character {
-    enemy: Idx,
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Character {
     idx: Idx,
 
     range: f32,
@@ -18,10 +26,13 @@ struct Character {
     walk_speed: i64,
     attack_speed: i64,
 
-    health: Cell<i32>,
     cooldown: Cell<i64>,
     wait_time: Cell<i64>,
     last_enemy: Cell<Option<(Idx, IPoint)>>,
+    #[serde(default)]
+    status: StatusEffects,
+    #[serde(default)]
+    profile: AiProfile,
 }
 
 impl Object for Character {
@@ -29,28 +40,29 @@ impl Object for Character {
         self.idx
     }
 
-    fn get_pixel(&self) -> Pixel {
-        return Pixel(Icon::Player, Color(255, 255, 255))
+    fn save(&self) -> ObjectData {
+        ObjectData::Character(self.clone())
     }
 
-    fn get_ordinal(&self) -> i32 {
-        0
+    fn abilities(&self) -> Option<Abilities> {
+        Some(Abilities {
+            sight_range: self.range,
+            walk_speed: self.walk_speed,
+            attack_speed: self.attack_speed,
+            wait_speed: self.wait_speed,
+        })
     }
 
-    fn is_environment(&self) -> bool {
-        false
+    fn status(&self) -> Option<&StatusEffects> {
+        Some(&self.status)
     }
 
-    fn is_blocking(&self) -> bool {
+    fn add_status(&mut self, effect: StatusEffect) -> bool {
+        self.status.add(effect);
         true
     }
-
-    fn is_opaque(&self) -> bool {
-        false
-    }
-
-    fn name(&self) -> &str {
-        "Character"
+    fn remove_status(&mut self, kind: StatusKind) -> bool {
+        self.status.remove(kind)
     }
 
     fn is_active(&self) -> bool {
@@ -58,35 +70,63 @@ impl Object for Character {
     }
 
     fn get_cooldown(&self) -> i64 {
-        if self.health.get() > 0 {
-            self.cooldown.get().max(self.wait_time.get())
-        } else {
-            i64::max_value()
-        }
+        self.cooldown.get().max(self.wait_time.get()).max(self.status.stunned())
     }
 
-    fn lapse_time(&mut self, interval: i64) {
+    fn lapse_time(&mut self, interval: i64) -> i32 {
         let new_cooldown = 0.max(self.cooldown.get() - interval);
         let new_wait_time = 0.max(self.wait_time.get() - interval);
         self.cooldown.set(new_cooldown);
         self.wait_time.set(new_wait_time);
+        self.status.lapse_time(interval)
     }
 
-    fn update(&self, context: Context, _effects: &mut Effects) {
+    // goes after the closest hostile creature in sight, the lower idx of equally close ones
+    fn update(&self, context: Context, effects: &mut Effects) {
+        self.forget_enemy(&context);
         let enemy = context.level.visible_points(context.position, self.range).into_iter()
             .flat_map(|p| context.level.get_tile(p).unwrap().iter())
-            .filter(|e| self.is_enemy(e))
-            .min_by_key(|e| OrderedFloat(e.position().dist(context.position)));
+            .filter(|e| self.is_enemy(&context, e))
+            .min_by_key(|e| (OrderedFloat(e.position().dist(context.position)), e.idx()));
         if let Some(e) = enemy {
-            self.last_enemy.set(Some((e.object().get_idx(), e.position())));
-            self.wait_time.set(0);
+            let enemy_idx = e.idx();
+            // a new enemy cuts waiting short, one already chased does not
+            if self.last_enemy.get().map(|(idx, _pos)| idx) != Some(enemy_idx) {
+                effects.events.push(Event::SpottedEnemy { idx: self.idx, enemy: enemy_idx });
+                self.wait_time.set(0);
+            }
+            self.last_enemy.set(Some((enemy_idx, e.position())));
         };
     }
 
+    // attack of opportunity when a hostile creature walks away from next to it
+    fn interrupt(&self, context: Context, action: &Action, _depth: usize, effects: &mut Effects) {
+        if let Action::Walk { idx, position } = *action {
+            let leaving = context.level.get_position(idx).map_or(false, |from|
+                from.neumann_dist(context.position) == 1 && position.neumann_dist(context.position) > 1
+            );
+            if leaving && context.world.relation(self.idx, idx) == Relation::Hostile {
+                effects.reactions.push(Action::Attack { source: self.idx, target: idx });
+            }
+        }
+    }
+
+    // counterattack, but only against attacks that were not reactions themselves;
+    // only living entities get to react
+    fn react(&self, _context: Context, action: &Action, outcome: &Outcome, depth: usize, effects: &mut Effects) {
+        if let Action::Attack { source, target } = *action {
+            if target == self.idx && depth == 0 && outcome.is_success() {
+                effects.reactions.push(Action::Attack { source: self.idx, target: source });
+            }
+        }
+    }
+
     fn plan_action(&self, context: Context, effects: &mut Effects) {
+        self.forget_enemy(&context);
         let mut ctx = context;
         let maybe_action: Option<Action>;
         match self.last_enemy.get() {
+            None if self.profile == AiProfile::Guard => maybe_action = None,
             None => maybe_action = self.plan_walk_random(&mut ctx, effects),
             Some((enemy_idx, enemy_pos)) => {
                 if enemy_pos.neumann_dist(ctx.position) > 1 {
@@ -104,38 +144,69 @@ impl Object for Character {
 
     fn execute_action(&mut self, _effects: &mut Effects, action: &Action) {
         match action {
-            Action::Attack { target, source } => {
+            Action::Attack { source, .. } => {
                 if *source == self.idx {
-                    self.cooldown.set(self.cooldown.get() + self.attack_speed)
-                }
-                if *target == self.idx {
-                    self.health.set(0.max(self.health.get() - 1))
+                    self.cooldown.set(self.cooldown.get() + self.status.action_cost(self.attack_speed))
                 }
             },
             Action::Walk { idx, .. } => {
-                self.cooldown.set(self.cooldown.get() + self.walk_speed)
+                self.cooldown.set(self.cooldown.get() + self.status.action_cost(self.walk_speed))
 
             }
             Action::Wait { idx, .. } => {
-                self.wait_time.set(self.wait_time.get() + self.wait_speed);
+                self.wait_time.set(self.wait_time.get() + self.status.action_cost(self.wait_speed));
             }
         }
     }
 }
 
 impl Character {
-    fn is_enemy(&self, entity: &Entity) -> bool {
-        entity.object().get_idx() == self.enemy
+    pub fn new(idx: Idx) -> Character {
+        Character {
+            idx,
+
+            range: 8.0,
+            wait_speed: 10,
+            walk_speed: 10,
+            attack_speed: 10,
+
+            cooldown: Cell::new(0),
+            wait_time: Cell::new(0),
+            last_enemy: Cell::new(None),
+            status: StatusEffects::default(),
+            profile: AiProfile::Hunter,
+        }
+    }
+    pub fn from_template(idx: Idx, template: &CreatureTemplate) -> Character {
+        Character {
+            range: template.sight_range,
+            wait_speed: template.wait_speed,
+            walk_speed: template.walk_speed,
+            attack_speed: template.attack_speed,
+            profile: template.ai,
+            ..Character::new(idx)
+        }
+    }
+
+    fn is_enemy(&self, context: &Context, entity: &Entity) -> bool {
+        entity.idx() != self.idx && entity.is_alive() && entity.components().faction.is_some() &&
+            context.world.relation(self.idx, entity.idx()) == Relation::Hostile
+    }
+
+    // the enemy chased may have died, left the level or made peace since it was seen
+    fn forget_enemy(&self, context: &Context) {
+        if let Some((idx, _pos)) = self.last_enemy.get() {
+            if !context.level.get_entity(idx).map_or(false, |e| self.is_enemy(context, e)) {
+                self.last_enemy.set(None);
+            }
+        }
     }
 
     fn walk_options(&self, context: &mut Context) -> Vec<IPoint> {
         context.position.neumann_surrounding()
             .into_iter()
-            .filter(|p| !context.level
-                .get_tile(context.position + *p)
-                .map(Level::is_blocking)
-                .unwrap_or(true)
-            ).collect()
+            .filter(|p| !context.level.is_blocking(*p))
+            .collect()
     }
 
     fn plan_walk_random(&self, context: &mut Context, effects: &mut Effects) -> Option<Action> {
@@ -167,3 +238,20 @@ impl Character {
         }
     }
 }
+
+impl From<Character> for Bundle {
+    fn from(character: Character) -> Bundle {
+        Bundle {
+            idx: character.idx,
+            components: Components {
+                render: Some(Render { pixel: Pixel(Icon::Player, Color(255, 255, 255)), ordinal: 0, environment: false }),
+                blocker: true,
+                health: Some(Health::new(10)),
+                combat: Some(Combat::default()),
+                faction: Some("monsters".to_string()),
+                ..Components::named("Character")
+            },
+            ai: Some(Box::new(character)),
+        }
+    }
+}
@@ -1,45 +1,17 @@
-use state::object::Object;
 use state::object::Idx;
 use state::object::Pixel;
 use state::object::Icon;
 use state::object::Color;
-
-pub struct Floor {
-    idx: Idx
-}
-
-impl Floor {
-    pub fn new(idx: Idx) -> Floor {
-        Floor {idx}
+use state::component::{Bundle, Components, Render};
+
+// nothing but components, drawn below everything else on its tile
+pub fn floor(idx: Idx) -> Bundle {
+    Bundle {
+        idx,
+        components: Components {
+            render: Some(Render { pixel: Pixel(Icon::Floor, Color(255, 255, 255)), ordinal: -1024, environment: true }),
+            ..Components::named("Floor")
+        },
+        ai: None,
     }
 }
-
-impl Object for Floor {
-    fn get_idx(&self) -> Idx {
-        self.idx
-    }
-
-    fn get_pixel(&self) -> Pixel {
-        Pixel(Icon::Floor, Color(255, 255, 255))
-    }
-
-    fn get_ordinal(&self) -> i32 {
-        -1024
-    }
-
-    fn is_environment(&self) -> bool {
-        true
-    }
-
-    fn is_blocking(&self) -> bool {
-        false
-    }
-
-    fn is_opaque(&self) -> bool {
-        false
-    }
-
-    fn name(&self) -> &str {
-        "Floor"
-    }
-}
\ No newline at end of file
@@ -1,4 +1,4 @@
-use state::object::{Idx, Color, Pixel, Icon, Object};
+use state::object::{Idx, Color, Pixel, Icon, Object, Abilities};
 use state::level::{Entity, Level};
 use utils::ipoint::IPoint;
 use utils::point::Point;
@@ -8,7 +8,13 @@ use state::context::Action;
 use rand::Rng;
 use std::cell::Cell;
 use state::context::Effects;
+use state::save::ObjectData;
+use state::component::{Bundle, Components, Health, Inventory, Render};
+use state::status::{StatusEffect, StatusEffects, StatusKind};
+use state::stats::Combat;
+use design::templates::CreatureTemplate;
 
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Player {
     idx: Idx,
 
@@ -17,10 +23,11 @@ pub struct Player {
     walk_speed: i64,
     attack_speed: i64,
 
-    health: i32,
     cooldown: i64,
     wait_time: i64,
     action: Action,
+    #[serde(default)]
+    status: StatusEffects,
 }
 
 impl Object for Player {
@@ -28,28 +35,29 @@ impl Object for Player {
         self.idx
     }
 
-    fn get_pixel(&self) -> Pixel {
-        return Pixel(Icon::Player, Color(255, 255, 255))
+    fn save(&self) -> ObjectData {
+        ObjectData::Player(self.clone())
     }
 
-    fn get_ordinal(&self) -> i32 {
-        0
+    fn abilities(&self) -> Option<Abilities> {
+        Some(Abilities {
+            sight_range: self.range,
+            walk_speed: self.walk_speed,
+            attack_speed: self.attack_speed,
+            wait_speed: self.wait_speed,
+        })
     }
 
-    fn is_environment(&self) -> bool {
-        false
+    fn status(&self) -> Option<&StatusEffects> {
+        Some(&self.status)
     }
 
-    fn is_blocking(&self) -> bool {
+    fn add_status(&mut self, effect: StatusEffect) -> bool {
+        self.status.add(effect);
         true
     }
-
-    fn is_opaque(&self) -> bool {
-        false
-    }
-
-    fn name(&self) -> &str {
-        "Character"
+    fn remove_status(&mut self, kind: StatusKind) -> bool {
+        self.status.remove(kind)
     }
 
     fn is_active(&self) -> bool {
@@ -57,12 +65,13 @@ impl Object for Player {
     }
 
     fn get_cooldown(&self) -> i64 {
-        self.cooldown.max(self.wait_time)
+        self.cooldown.max(self.wait_time).max(self.status.stunned())
     }
 
-    fn lapse_time(&mut self, interval: i64) {
+    fn lapse_time(&mut self, interval: i64) -> i32 {
         self.cooldown = 0.max(self.cooldown - interval);
         self.wait_time = 0.max(self.wait_time - interval);
+        self.status.lapse_time(interval)
     }
 
     fn update(&self, context: Context, _effects: &mut Effects) {
@@ -74,20 +83,17 @@ impl Object for Player {
 
     fn execute_action(&mut self, _effects: &mut Effects, action: &Action) {
         match action {
-            Action::Attack { target, source } => {
+            Action::Attack { source, .. } => {
                 if *source == self.idx {
-                    self.cooldown = self.cooldown + self.attack_speed
-                }
-                if *target == self.idx {
-                    self.health = 0.max(self.health - 1)
+                    self.cooldown = self.cooldown + self.status.action_cost(self.attack_speed)
                 }
             },
             Action::Walk { idx, .. } => {
-                self.cooldown = self.cooldown + self.walk_speed
+                self.cooldown = self.cooldown + self.status.action_cost(self.walk_speed)
 
             }
             Action::Wait { idx, .. } => {
-                self.wait_time = self.wait_speed;
+                self.wait_time = self.status.action_cost(self.wait_speed);
             }
         }
     }
@@ -111,10 +117,37 @@ impl Player {
             walk_speed: 10,
             attack_speed: 10,
 
-            health: 10,
             cooldown: 0,
             wait_time: 0,
             action: Action::Wait{idx},
+            status: StatusEffects::default(),
+        }
+    }
+    pub fn from_template(idx: Idx, template: &CreatureTemplate) -> Player {
+        Player {
+            range: template.sight_range,
+            wait_speed: template.wait_speed,
+            walk_speed: template.walk_speed,
+            attack_speed: template.attack_speed,
+            ..Player::new(idx)
+        }
+    }
+}
+
+impl From<Player> for Bundle {
+    fn from(player: Player) -> Bundle {
+        Bundle {
+            idx: player.idx,
+            components: Components {
+                render: Some(Render { pixel: Pixel(Icon::Player, Color(255, 255, 255)), ordinal: 0, environment: false }),
+                blocker: true,
+                health: Some(Health::new(10)),
+                inventory: Some(Inventory::default()),
+                combat: Some(Combat::default()),
+                faction: Some("players".to_string()),
+                ..Components::named("Character")
+            },
+            ai: Some(Box::new(player)),
         }
     }
 }
@@ -1,45 +1,19 @@
-use state::object::Object;
 use state::object::Idx;
 use state::object::Pixel;
 use state::object::Icon;
 use state::object::Color;
-
-pub struct Wall {
-    idx: Idx
-}
-
-impl Wall {
-    pub fn new(idx: Idx) -> Wall {
-        Wall {idx}
+use state::component::{Bundle, Components, Render};
+
+// nothing but components, walls have no ai
+pub fn wall(idx: Idx) -> Bundle {
+    Bundle {
+        idx,
+        components: Components {
+            render: Some(Render { pixel: Pixel(Icon::Wall, Color(255, 255, 255)), ordinal: 0, environment: true }),
+            blocker: true,
+            opaque: true,
+            ..Components::named("Wall")
+        },
+        ai: None,
     }
 }
-
-impl Object for Wall {
-    fn get_idx(&self) -> Idx {
-        self.idx
-    }
-
-    fn get_pixel(&self) -> Pixel {
-        Pixel(Icon::Wall, Color(255, 255, 255))
-    }
-
-    fn get_ordinal(&self) -> i32 {
-        0
-    }
-
-    fn is_environment(&self) -> bool {
-        true
-    }
-
-    fn is_blocking(&self) -> bool {
-        true
-    }
-
-    fn is_opaque(&self) -> bool {
-        true
-    }
-
-    fn name(&self) -> &str {
-        "Wall"
-    }
-}
\ No newline at end of file
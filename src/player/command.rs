@@ -0,0 +1,64 @@
+use state::object::Idx;
+use utils::ipoint::IPoint;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    North,
+    South,
+    East,
+    West,
+}
+
+impl Direction {
+    pub fn offset(self) -> IPoint {
+        match self {
+            Direction::North => IPoint { x: 0, y: -1 },
+            Direction::South => IPoint { x: 0, y: 1 },
+            Direction::East => IPoint { x: 1, y: 0 },
+            Direction::West => IPoint { x: -1, y: 0 },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Command {
+    Move { dir: Direction },
+    Wait,
+    Attack { target: Idx },
+    // move until something interesting happens, see GameState::repeat
+    Run { dir: Direction },
+    // wait until healed or until something interesting happens
+    Rest,
+    // the last single step command again, up to count times
+    Repeat { count: u32 },
+}
+
+impl Command {
+    // the command a multi turn command repeats, None for single step commands
+    pub fn step(&self) -> Option<Command> {
+        match *self {
+            Command::Run { dir } => Some(Command::Move { dir }),
+            Command::Rest => Some(Command::Wait),
+            _ => None,
+        }
+    }
+
+    // default keyboard layout, kept for press_key: shifted direction keys run, r rests
+    pub fn from_key(key: &str) -> Option<Command> {
+        let dir = match key.to_lowercase().as_str() {
+            "w" => Direction::North,
+            "s" => Direction::South,
+            "a" => Direction::West,
+            "d" => Direction::East,
+            "r" => return Some(Command::Rest),
+            _ => return None,
+        };
+        if key.to_lowercase() == key {
+            Some(Command::Move { dir })
+        } else {
+            Some(Command::Run { dir })
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use state::object::Idx;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum DeathCause {
+    Killed { by: Idx, name: String },
+    // a poison tick took the last of the player's health
+    Poisoned,
+    // health lost some other way, like being set from outside
+    Unknown,
+}
+
+impl DeathCause {
+    pub fn message(&self, turn: u64) -> String {
+        match self {
+            DeathCause::Killed { name, .. } => format!("You were killed by the {} on turn {}.", name, turn),
+            DeathCause::Poisoned => format!("You succumbed to poison on turn {}.", turn),
+            DeathCause::Unknown => format!("You died on turn {}.", turn),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "state", rename_all = "lowercase")]
+pub enum GameStatus {
+    Playing,
+    // every player is dead, the cause is the last one's
+    Dead { cause: DeathCause, message: String },
+    // every other creature is dead
+    Won,
+}
+
+impl Default for GameStatus {
+    fn default() -> GameStatus {
+        GameStatus::Playing
+    }
+}
@@ -1 +1,3 @@
-pub mod player;
\ No newline at end of file
+pub mod player;
+pub mod command;
+pub mod lifecycle;
@@ -5,111 +5,270 @@ use state::world::World;
 use utils::ipoint::IPoint;
 use state::level::Level;
 use state::object::Pixel;
-use state::context::Action;
+use state::object::PublicStats;
+use state::context::{Action, Outcome};
 use state::level::Entity;
+use utils::pointmap;
+use error::{GameError, GameResult};
+use player::command::Command;
+use state::context::Effects;
+use state::event::{Event, StopReason, TimedEvent};
+use state::status::StatusEffect;
+use player::lifecycle::DeathCause;
 
 pub struct View {
     pub tiles: HashMap<IPoint, Pixel>,
     pub size: IPoint,
+    pub level: Idx,
 }
 
+#[derive(Serialize)]
+pub struct PixelChange {
+    pub x: i32,
+    pub y: i32,
+    pub pixel: Pixel,
+}
+
+#[derive(Serialize)]
+pub struct ViewDiff {
+    pub full: bool,
+    pub size: IPoint,
+    pub changes: Vec<PixelChange>,
+}
+
+impl View {
+    pub fn pixel(&self, position: IPoint) -> Pixel {
+        self.tiles.get(&position).map_or(Pixel::empty(), |p| *p)
+    }
+
+    // cells that differ from the previous view, or every cell if there is nothing to compare with
+    pub fn diff(&self, previous: Option<&View>) -> ViewDiff {
+        let previous = previous.filter(|v| v.level == self.level && v.size == self.size);
+        let changes = self.size.zrange().iter()
+            .map(|p| (p, self.pixel(p)))
+            .filter(|(p, pixel)| previous.map_or(true, |v| v.pixel(*p) != *pixel))
+            .map(|(p, pixel)| PixelChange { x: p.x, y: p.y, pixel })
+            .collect();
+        ViewDiff {
+            full: previous.is_none(),
+            size: self.size,
+            changes,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct EntityInfo {
+    pub idx: Idx,
+    pub name: String,
+    pub pixel: Pixel,
+    pub stats: Option<PublicStats>,
+}
+
+// only tiles in sight show their entities, remembered tiles show what the player saw last time
+#[derive(Serialize)]
+#[serde(tag = "visibility", rename_all = "lowercase")]
+pub enum Inspection {
+    Visible { entities: Vec<EntityInfo> },
+    Remembered { pixel: Pixel },
+    Unknown,
+}
+
+#[derive(Serialize)]
+pub struct PlayerStatus {
+    pub idx: Idx,
+    pub stats: Option<PublicStats>,
+    pub effects: Vec<StatusEffect>,
+    // world time
+    pub time: i64,
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct PlayerData {
     pub player: Idx,
+    #[serde(with = "pointmap::nested")]
     pub views: HashMap<Idx, HashMap<IPoint, Pixel>>,
     pub messages: Vec<String>,
-    range: f32,
+    #[serde(default)]
+    pub events: Vec<TimedEvent>,
+    #[serde(default)]
+    pub turn: u64,
+    #[serde(default)]
+    next_event: u64,
+    #[serde(default)]
+    pub death: Option<DeathCause>,
+    // for Command::Repeat
+    #[serde(default)]
+    pub last_command: Option<Command>,
 }
 
+// oldest events are dropped past this many
+const MAX_EVENTS: usize = 1000;
+
 impl PlayerData {
     pub fn new(idx: Idx) -> PlayerData {
         PlayerData {
             player: idx,
             views: HashMap::new(),
             messages: Vec::new(),
-            range: 8.0,
+            events: Vec::new(),
+            turn: 0,
+            next_event: 0,
+            death: None,
+            last_command: None,
         }
 
     }
 
-    pub fn wait<'a>(&mut self, game: &'a mut World) {
-        let entity_idx = game.lapse_time().unwrap().object().get_idx();
-        if entity_idx != self.player {
-            game.invoke_actions(entity_idx);
-            self.wait(game)
-        }
+    // as far as the player's entity sees, nothing without one
+    fn sight_range(&self, level: &Level) -> f32 {
+        level.get_entity(self.player)
+            .and_then(|e| e.ai())
+            .and_then(|ai| ai.abilities())
+            .map_or(0.0, |a| a.sight_range)
     }
 
-    pub fn process_key(&mut self, game: &mut World, string: &str) {
-        self.wait(game);
+    // keeps messages, and events the player could see or took part in
+    pub fn record(&mut self, game: &World, effects: &Effects) -> GameResult<()> {
+        self.messages.extend(effects.messages.iter().cloned());
 
-        let dir: IPoint = match string {
-            "w" => IPoint {x: 0, y: -1},
-            "s" => IPoint {x: 0, y: 1},
-            "a" => IPoint {x: -1, y: 0},
-            "d" => IPoint {x: 1, y: 0},
-            _ => return,
+        let idx = self.player;
+        let level = game.get_entity_level(idx).ok_or(GameError::MissingEntity { idx })?;
+        let position = level.get_position(idx).ok_or(GameError::MissingEntity { idx })?;
+        let visible = level.visible_points(position, self.sight_range(level));
+        // the points of an event are on its actors' level
+        let seen = |event: &Event| {
+            let actors = event.actors();
+            actors.iter().any(|a| *a == idx ||
+                level.get_position(*a).map_or(false, |p| visible.contains(&p))
+            ) || (actors.iter().any(|a| level.get_entity(*a).is_some()) &&
+                event.points().iter().any(|p| visible.contains(p)))
         };
+        for event in effects.events.iter().filter(|e| seen(e)) {
+            self.note(game.time(), *event);
+            if let Some(message) = self.describe(game, event) {
+                self.messages.push(message);
+            }
+        }
+        Ok(())
+    }
 
-        let player = game.get_mut_entity(self.player).unwrap();
-        let position = player.position();
-        match player.object_mut().as_player() {
-            None => {panic!()},
-            Some(p) => {
-                p.set_action(Action::Walk {
-                    idx: self.player,
-                    position: dir + position
-                });
-            },
+    // a line about a fight, from the player's point of view
+    fn describe(&self, game: &World, event: &Event) -> Option<String> {
+        let name = |idx: Idx| if idx == self.player {
+            "you".to_string()
+        } else {
+            format!("the {}", game.get_entity(idx).map_or("something", |e| e.name()))
+        };
+        let verb = |idx: Idx, you: &'static str, other: &'static str| if idx == self.player { you } else { other };
+        let line = match *event {
+            Event::Missed { source, target } =>
+                format!("{} {} {}.", name(source), verb(source, "miss", "misses"), name(target)),
+            Event::Hit { source, target, damage, critical: false } =>
+                format!("{} {} {} for {}.", name(source), verb(source, "hit", "hits"), name(target), damage),
+            Event::Hit { source, target, damage, critical: true } =>
+                format!("{} critically {} {} for {}!", name(source), verb(source, "hit", "hits"), name(target), damage),
+            _ => return None,
+        };
+        let mut chars = line.chars();
+        chars.next().map(|first| first.to_uppercase().chain(chars).collect())
+    }
+
+    pub fn note(&mut self, time: i64, event: Event) {
+        self.events.push(TimedEvent { id: self.next_event, time, turn: self.turn, event });
+        self.next_event += 1;
+        if self.events.len() > MAX_EVENTS {
+            let excess = self.events.len() - MAX_EVENTS;
+            self.events.drain(..excess);
+        }
+    }
+
+    // ends a run, rest or repeat, telling the player why
+    pub fn stop(&mut self, time: i64, reason: StopReason) {
+        let idx = self.player;
+        self.note(time, Event::Stopped { idx, reason });
+        if let Some(message) = reason.message() {
+            self.messages.push(message);
         }
+    }
+
+    // living creatures in sight other than the player, in idx order
+    pub fn visible_creatures(&self, game: &World) -> GameResult<Vec<Idx>> {
+        let idx = self.player;
+        let level = game.get_entity_level(idx).ok_or(GameError::MissingEntity { idx })?;
+        let position = level.get_position(idx).ok_or(GameError::MissingEntity { idx })?;
+        let mut creatures: Vec<Idx> = level.visible_points(position, self.sight_range(level)).into_iter()
+            .filter_map(|p| level.get_tile(p))
+            .flat_map(|tile| tile.iter())
+            .filter(|e| e.is_alive())
+            .map(|e| e.idx())
+            .filter(|i| *i != idx)
+            .collect();
+        creatures.sort();
+        Ok(creatures)
+    }
 
-        /*
-        let level = game.get_mut_entity_level(self.player).unwrap();
-        let entity = level.get_mut_entity(self.player).unwrap();
-        let position = entity.position();
-        let new_pos = dir + position;
-        if !level.size().zrange().inside(new_pos) {
-            self.messages.push("you hit the wall".to_string());
-            return;
+    pub fn get_events(&self, since: i64) -> Vec<&TimedEvent> {
+        self.events.iter().filter(|e| e.id as i64 > since).collect()
+    }
+
+    // gives the player's entity the action for the command, the world decides whether it works
+    pub fn set_command(&self, game: &mut World, command: Command) -> GameResult<()> {
+        let idx = self.player;
+        let action = self.command_action(game, command)?;
+        let player = game.get_mut_entity(idx).ok_or(GameError::MissingEntity { idx })?;
+        match player.as_player() {
+            None => Err(GameError::NotAPlayer { idx }),
+            Some(p) => Ok(p.set_action(action)),
         }
+    }
 
-        let tile = level.get_tile(new_pos).unwrap();
-        let obstacle = tile.iter().filter(|e| e.object().is_blocking()).next();
-        match obstacle {
-            Some(obs) => self.messages.push("you hit the ".to_string() + &obs.object().name()),
-            None => entity.object_mut().set_action(Action::Walk{
-                idx: self.player,
-                position: new_pos,
-            })
-        }*/
-        game.invoke_actions(self.player);
-        self.wait(game);
-        self.update_memory(game.get_mut_entity_level(self.player).unwrap());
-    }
-
-    fn update_memory(&mut self, level: &mut Level) {
-        let player = level.get_entity(self.player).unwrap();
-        let visible = level.visible_points(player.position(), self.range);
+    fn command_action(&self, game: &World, command: Command) -> GameResult<Action> {
+        let idx = self.player;
+        let position = game.get_entity(idx).ok_or(GameError::MissingEntity { idx })?.position();
+        Ok(match command {
+            Command::Move { dir } => Action::Walk { idx, position: position + dir.offset() },
+            Command::Wait => Action::Wait { idx },
+            Command::Attack { target } => Action::Attack { source: idx, target },
+            Command::Run { .. } | Command::Rest | Command::Repeat { .. } => return Err(GameError::NotSingleStep),
+        })
+    }
+
+    // tells the player why their action did not work
+    pub fn report(&mut self, outcome: &Outcome) {
+        if let Some(message) = outcome.message() {
+            self.messages.push(message);
+        }
+    }
+
+    pub fn update_memory(&mut self, game: &World) -> GameResult<()> {
+        let idx = self.player;
+        let level = game.get_entity_level(idx).ok_or(GameError::MissingEntity { idx })?;
+        let player = level.get_entity(idx).ok_or(GameError::MissingEntity { idx })?;
+        let visible = level.visible_points(player.position(), self.sight_range(level));
         let pixels: HashMap<IPoint, Pixel> =
             visible.iter()
-                .map(|k| (*k, Level::build_mem_pixel(level.tiles().get(k).unwrap())))
+                .filter_map(|k| level.get_tile(*k).map(|t| (*k, Level::build_mem_pixel(t))))
                 .collect();
 
         match self.views.entry(level.idx()) {
             Entry::Occupied(mut e) => { e.get_mut().extend(pixels); }
             Entry::Vacant(e) => { e.insert(pixels); }
         };
+        Ok(())
     }
 
-    pub fn build_view(&self, game: &World) -> View {
-        let level = game.get_entity_level(self.player).unwrap();
-        let player = level.get_entity(self.player).unwrap();
+    pub fn build_view(&self, game: &World) -> GameResult<View> {
+        let idx = self.player;
+        let level = game.get_entity_level(idx).ok_or(GameError::MissingEntity { idx })?;
+        let player = level.get_entity(idx).ok_or(GameError::MissingEntity { idx })?;
         let position = player.position();
 
-        let visible = level.visible_points(position, self.range);
+        let visible = level.visible_points(position, self.sight_range(level));
 
         let mut current_pixels: HashMap<IPoint, Pixel> =
             visible.into_iter()
-                .map(|p| (p, Level::build_pixel(level.get_tile(p).unwrap())))
+                .filter_map(|p| level.get_tile(p).map(|t| (p, Level::build_pixel(t))))
                 .collect();
 
         match self.views.get(&level.idx()) {
@@ -124,10 +283,49 @@ impl PlayerData {
             }
         };
 
-        View {
+        Ok(View {
             size: level.size(),
             tiles: current_pixels,
+            level: level.idx(),
+        })
+    }
+
+    pub fn inspect(&self, game: &World, position: IPoint) -> GameResult<Inspection> {
+        let idx = self.player;
+        let level = game.get_entity_level(idx).ok_or(GameError::MissingEntity { idx })?;
+        let tile = level.get_tile(position)
+            .ok_or(GameError::OutsideLevel { level: level.idx(), position })?;
+        let player = level.get_position(idx).ok_or(GameError::MissingEntity { idx })?;
+
+        if level.visible_points(player, self.sight_range(level)).contains(&position) {
+            let mut entities: Vec<&Entity> = tile.iter().collect();
+            entities.sort_by_key(|e| -e.components().render.map_or(0, |r| r.ordinal));
+            let entities = entities.into_iter()
+                .map(|e| EntityInfo {
+                    idx: e.idx(),
+                    name: e.name().to_string(),
+                    pixel: e.components().render.map_or(Pixel::empty(), |r| r.pixel),
+                    stats: e.public_stats(),
+                })
+                .collect();
+            return Ok(Inspection::Visible { entities });
         }
+        let remembered = self.views.get(&level.idx()).and_then(|v| v.get(&position));
+        Ok(match remembered {
+            None => Inspection::Unknown,
+            Some(pixel) => Inspection::Remembered { pixel: *pixel },
+        })
+    }
+
+    pub fn status(&self, game: &World) -> GameResult<PlayerStatus> {
+        let idx = self.player;
+        let player = game.get_entity(idx).ok_or(GameError::MissingEntity { idx })?;
+        Ok(PlayerStatus {
+            idx,
+            stats: player.public_stats(),
+            effects: player.ai().and_then(|ai| ai.status()).map_or(Vec::new(), |s| s.list().to_vec()),
+            time: game.time(),
+        })
     }
 
     pub fn get_messages(&self) -> &Vec<String> {
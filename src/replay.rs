@@ -0,0 +1,63 @@
+use foundation::GameState;
+use design::config::GameConfig;
+use player::command::Command;
+use error::{GameError, GameResult};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Input {
+    Key { key: String },
+    Command { command: Command },
+    AddPlayer,
+    Undo { count: usize },
+    Redo { count: usize },
+    Restart,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Step {
+    pub input: Input,
+    // World::state_hash after the input, as hex so javascript keeps every bit
+    pub hash: String,
+}
+
+// everything needed to play a game again: the config holds both seeds
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Recording {
+    pub config: GameConfig,
+    pub start: String,
+    pub steps: Vec<Step>,
+}
+
+pub fn format_hash(hash: u64) -> String {
+    format!("{:016x}", hash)
+}
+
+fn check(step: usize, expected: &str, state: &GameState) -> GameResult<()> {
+    let found = format_hash(state.game.state_hash()?);
+    if found != expected {
+        return Err(GameError::ReplayDiverged { step, expected: expected.to_string(), found });
+    }
+    Ok(())
+}
+
+// rebuilds the game from the config and feeds it every input again, comparing state hashes
+// after each one; step 0 is the state before the first input. The returned game keeps recording.
+pub fn replay(recording: &Recording) -> GameResult<GameState> {
+    let mut state = GameState::from_config(recording.config.clone())?;
+    check(0, &recording.start, &state)?;
+    state.start_recording()?;
+
+    for (i, step) in recording.steps.iter().enumerate() {
+        match step.input {
+            Input::Key { ref key } => state.process_key(key)?,
+            Input::Command { command } => { state.submit_command(command)?; }
+            Input::AddPlayer => { state.add_player()?; }
+            Input::Undo { count } => { state.undo(count)?; }
+            Input::Redo { count } => { state.redo(count)?; }
+            Input::Restart => state.restart()?,
+        }
+        check(i + 1, &step.hash, &state)?;
+    }
+    Ok(state)
+}
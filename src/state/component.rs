@@ -0,0 +1,105 @@
+use state::object::{Idx, Object, Pixel};
+use state::stats::Combat;
+
+// kinds of components the level keeps an index of; every entity also has a position,
+// see Entity::position
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum ComponentKind {
+    Render,
+    Blocker,
+    Opaque,
+    Health,
+    Ai,
+    Inventory,
+    Combat,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Render {
+    pub pixel: Pixel,
+    // the highest one on a tile is drawn
+    pub ordinal: i32,
+    // remembered once seen, drawn gray while out of sight
+    pub environment: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Health {
+    pub health: i32,
+    pub max_health: i32,
+}
+
+impl Health {
+    pub fn new(max_health: i32) -> Health {
+        Health { health: max_health, max_health }
+    }
+    pub fn is_alive(&self) -> bool {
+        self.health > 0
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Inventory {
+    pub items: Vec<Idx>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+// the data an entity is made of, its behaviour is the ai next to it;
+// missing components are left out of saves, levels hold thousands of walls and floors
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Components {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub render: Option<Render>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub blocker: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub opaque: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health: Option<Health>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inventory: Option<Inventory>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub combat: Option<Combat>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub faction: Option<String>,
+}
+
+impl Components {
+    pub fn named(name: &str) -> Components {
+        Components { name: name.to_string(), ..Components::default() }
+    }
+    pub fn kinds(&self) -> Vec<ComponentKind> {
+        let mut kinds = Vec::new();
+        if self.render.is_some() {
+            kinds.push(ComponentKind::Render);
+        }
+        if self.blocker {
+            kinds.push(ComponentKind::Blocker);
+        }
+        if self.opaque {
+            kinds.push(ComponentKind::Opaque);
+        }
+        if self.health.is_some() {
+            kinds.push(ComponentKind::Health);
+        }
+        if self.inventory.is_some() {
+            kinds.push(ComponentKind::Inventory);
+        }
+        if self.combat.is_some() {
+            kinds.push(ComponentKind::Combat);
+        }
+        kinds
+    }
+}
+
+// an entity before it is placed on a level
+pub struct Bundle {
+    pub idx: Idx,
+    pub components: Components,
+    pub ai: Option<Box<Object>>,
+}
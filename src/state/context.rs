@@ -1,10 +1,11 @@
 use state::object::Idx;
 use state::world::World;
 use state::level::Level;
-use rand::XorShiftRng;
+use utils::xorshift::XorShift;
 use utils::ipoint::IPoint;
+use state::event::Event;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
 pub enum Action {
     Attack{source: Idx, target: Idx},
     Walk{idx: Idx, position: IPoint},
@@ -21,6 +22,36 @@ impl Action {
     }
 }
 
+// what came of an action, only a success costs the actor time
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub enum Outcome {
+    Succeeded,
+    Blocked { by: Idx, name: String },
+    OutOfBounds,
+    InvalidTarget,
+    TargetDead,
+    // the actor died before the action resolved
+    Interrupted,
+}
+
+impl Outcome {
+    pub fn is_success(&self) -> bool {
+        *self == Outcome::Succeeded
+    }
+    // what the player is told when their action fails
+    pub fn message(&self) -> Option<String> {
+        match self {
+            Outcome::Succeeded => None,
+            Outcome::Blocked { name, .. } => Some(format!("You hit the {}.", name.to_lowercase())),
+            Outcome::OutOfBounds => Some("You can not leave the map.".to_string()),
+            Outcome::InvalidTarget => Some("There is nothing to attack there.".to_string()),
+            Outcome::TargetDead => Some("It is already dead.".to_string()),
+            Outcome::Interrupted => Some("You were interrupted.".to_string()),
+        }
+    }
+}
+
 pub struct Context<'a> {
     pub world: &'a World,
     pub level: &'a Level,
@@ -28,7 +59,12 @@ pub struct Context<'a> {
 }
 
 pub struct Effects {
-    pub rand: XorShiftRng,
+    pub rand: XorShift,
     pub messages: Vec<String>,
-    pub actions: Vec<Action>
+    pub events: Vec<Event>,
+    pub actions: Vec<Action>,
+    // pushed by Object::interrupt and Object::react
+    pub reactions: Vec<Action>,
+    // of the action the entity planned, set by World::invoke_actions
+    pub outcome: Option<Outcome>,
 }
\ No newline at end of file
@@ -0,0 +1,83 @@
+use state::object::Idx;
+use utils::ipoint::IPoint;
+
+// why a run, rest or repeat ended
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "reason", rename_all = "lowercase")]
+pub enum StopReason {
+    Completed,
+    // the step did not work, the outcome's message says why
+    Failed,
+    Junction,
+    EnemyInSight { enemy: Idx },
+    Damaged { amount: i32 },
+    Healed,
+    // another player's turn came up
+    TurnPassed,
+    GameOver,
+}
+
+impl StopReason {
+    pub fn message(&self) -> Option<String> {
+        match self {
+            StopReason::EnemyInSight { .. } => Some("You see an enemy.".to_string()),
+            StopReason::Damaged { amount } => Some(format!("You lose {} health and stop.", amount)),
+            StopReason::Healed => Some("You feel rested.".to_string()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub enum Event {
+    Moved { idx: Idx, from: IPoint, to: IPoint },
+    Attacked { source: Idx, target: Idx },
+    // how an attack went, hits are followed by the damage
+    Hit { source: Idx, target: Idx, damage: i32, critical: bool },
+    Missed { source: Idx, target: Idx },
+    Damaged { idx: Idx, amount: i32, health: i32 },
+    Died { idx: Idx },
+    Blocked { idx: Idx, position: IPoint, by: Option<Idx> },
+    SpottedEnemy { idx: Idx, enemy: Idx },
+    Waited { idx: Idx },
+    Stopped { idx: Idx, reason: StopReason },
+    Spawned { idx: Idx, position: IPoint },
+    // the target's faction turned hostile to the source's
+    Provoked { source: Idx, target: Idx },
+}
+
+impl Event {
+    pub fn actors(&self) -> Vec<Idx> {
+        match self {
+            Event::Moved { idx, .. } => vec![*idx],
+            Event::Attacked { source, target } => vec![*source, *target],
+            Event::Hit { source, target, .. } => vec![*source, *target],
+            Event::Missed { source, target } => vec![*source, *target],
+            Event::Damaged { idx, .. } => vec![*idx],
+            Event::Died { idx } => vec![*idx],
+            Event::Blocked { idx, by, .. } => vec![Some(*idx), *by].into_iter().flatten().collect(),
+            Event::SpottedEnemy { idx, enemy } => vec![*idx, *enemy],
+            Event::Waited { idx } => vec![*idx],
+            Event::Stopped { idx, .. } => vec![*idx],
+            Event::Spawned { idx, .. } => vec![*idx],
+            Event::Provoked { source, target } => vec![*source, *target],
+        }
+    }
+    // places, other than the actors' current positions, where the event could be seen
+    pub fn points(&self) -> Vec<IPoint> {
+        match self {
+            Event::Moved { from, to, .. } => vec![*from, *to],
+            Event::Blocked { position, .. } => vec![*position],
+            _ => vec![],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct TimedEvent {
+    pub id: u64,
+    pub time: i64,
+    pub turn: u64,
+    pub event: Event,
+}
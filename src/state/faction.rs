@@ -0,0 +1,78 @@
+// how two factions stand towards each other
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Relation {
+    Hostile,
+    Neutral,
+    Allied,
+}
+
+// the same both ways, a faction hostile to itself fights among itself
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Relationship {
+    pub between: (String, String),
+    pub relation: Relation,
+}
+
+// the relationship matrix; pairs not in it are allied within a faction and neutral otherwise
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Factions {
+    // ordered by between, with the names of each pair in order
+    relations: Vec<Relationship>,
+}
+
+impl Default for Factions {
+    fn default() -> Factions {
+        Factions::new(&Factions::builtin()).expect("builtin relations are valid")
+    }
+}
+
+fn key(a: &str, b: &str) -> (String, String) {
+    if a <= b { (a.to_string(), b.to_string()) } else { (b.to_string(), a.to_string()) }
+}
+
+impl Factions {
+    // the factions of the builtin creature templates
+    pub fn builtin() -> Vec<Relationship> {
+        vec![Relationship { between: key("monsters", "players"), relation: Relation::Hostile }]
+    }
+
+    pub fn new(relations: &[Relationship]) -> Result<Factions, String> {
+        let mut factions = Factions { relations: Vec::new() };
+        for (i, r) in relations.iter().enumerate() {
+            if r.between.0.is_empty() || r.between.1.is_empty() {
+                return Err(format!("relations[{}].between can not name an empty faction", i));
+            }
+            let between = key(&r.between.0, &r.between.1);
+            if factions.find(&between).is_ok() {
+                return Err(format!("relations[{}].between {} and {} is set twice", i, between.0, between.1));
+            }
+            factions.set(&between.0, &between.1, r.relation);
+        }
+        Ok(factions)
+    }
+
+    fn find(&self, between: &(String, String)) -> Result<usize, usize> {
+        self.relations.binary_search_by(|r| r.between.cmp(between))
+    }
+
+    pub fn relations(&self) -> &[Relationship] {
+        &self.relations
+    }
+
+    pub fn relation(&self, a: &str, b: &str) -> Relation {
+        match self.find(&key(a, b)) {
+            Ok(i) => self.relations[i].relation,
+            Err(_) if a == b => Relation::Allied,
+            Err(_) => Relation::Neutral,
+        }
+    }
+
+    pub fn set(&mut self, a: &str, b: &str, relation: Relation) {
+        let between = key(a, b);
+        match self.find(&between) {
+            Ok(i) => self.relations[i].relation = relation,
+            Err(i) => self.relations.insert(i, Relationship { between, relation }),
+        }
+    }
+}
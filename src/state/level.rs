@@ -1,31 +1,134 @@
 use std::collections::HashMap;
 use std::collections::hash_map::Entry;
-use state::object::{Idx};
+use std::collections::{BTreeMap, BTreeSet};
+use state::object::{Idx, PublicStats};
 use utils::ipoint::IPoint;
 use state::object::Object;
 use state::object::Pixel;
+use state::component::{Bundle, ComponentKind, Components, Health, Inventory};
+use objects::player::Player;
+use serde::{Deserialize, Deserializer};
 use std::collections::HashSet;
 use logic::visibility::visibility_set;
 use std::ops::Deref;
 use std::ops::DerefMut;
+use std::mem;
 use utils::point::Point;
+use utils::pointmap;
+use error::{GameError, GameResult};
 
+#[derive(Serialize)]
 pub struct Level {
     idx: Idx,
     size: IPoint,
+    #[serde(with = "pointmap")]
     tiles: HashMap<IPoint, Vec<Entity>>,
-    positions: HashMap<Idx, IPoint>
+    positions: HashMap<Idx, IPoint>,
+    // active entities added since the world last looked, for its scheduler
+    #[serde(skip)]
+    added: Vec<Idx>,
+    policy: SimulationPolicy,
+    // while paused the level's active entities are out of the schedule,
+    // each with the time it fell asleep
+    paused: bool,
+    sleeping: Vec<(Idx, i64)>,
+    // rebuilt from the tiles when loading
+    #[serde(skip)]
+    index: ComponentIndex,
 }
 
+#[derive(Deserialize)]
+struct LevelData {
+    idx: Idx,
+    size: IPoint,
+    #[serde(with = "pointmap")]
+    tiles: HashMap<IPoint, Vec<Entity>>,
+    positions: HashMap<Idx, IPoint>,
+    #[serde(default)]
+    policy: SimulationPolicy,
+    #[serde(default)]
+    paused: bool,
+    #[serde(default)]
+    sleeping: Vec<(Idx, i64)>,
+}
+
+impl<'de> Deserialize<'de> for Level {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = LevelData::deserialize(deserializer)?;
+        let mut index = ComponentIndex::default();
+        for entity in data.tiles.values().flat_map(|tile| tile.iter()) {
+            index.insert(entity);
+        }
+        Ok(Level {
+            idx: data.idx,
+            size: data.size,
+            tiles: data.tiles,
+            positions: data.positions,
+            added: Vec::new(),
+            policy: data.policy,
+            paused: data.paused,
+            sleeping: data.sleeping,
+            index,
+        })
+    }
+}
+
+// entities by kind of component, and how many blockers and opaque entities each tile holds
+#[derive(Default)]
+struct ComponentIndex {
+    kinds: BTreeMap<ComponentKind, BTreeSet<Idx>>,
+    blocking: HashMap<IPoint, u32>,
+    opaque: HashMap<IPoint, u32>,
+}
+
+impl ComponentIndex {
+    fn insert(&mut self, entity: &Entity) {
+        for kind in entity.kinds() {
+            self.kinds.entry(kind).or_insert_with(BTreeSet::new).insert(entity.idx);
+        }
+        if entity.components.blocker {
+            *self.blocking.entry(entity.position).or_insert(0) += 1;
+        }
+        if entity.components.opaque {
+            *self.opaque.entry(entity.position).or_insert(0) += 1;
+        }
+    }
+    fn remove(&mut self, entity: &Entity) {
+        for kind in entity.kinds() {
+            if let Some(set) = self.kinds.get_mut(&kind) {
+                set.remove(&entity.idx);
+            }
+        }
+        for (has, counts) in vec![(entity.components.blocker, &mut self.blocking), (entity.components.opaque, &mut self.opaque)] {
+            if !has {
+                continue;
+            }
+            if let Entry::Occupied(mut e) = counts.entry(entity.position) {
+                *e.get_mut() -= 1;
+                if *e.get() == 0 {
+                    e.remove();
+                }
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct Entity {
-    object: Box<Object>,
+    idx: Idx,
+    components: Components,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ai: Option<Box<Object>>,
     level: Idx,
     position: IPoint
 }
 
 impl Entity {
-    fn new(object: Box<Object>, level: Idx, position: IPoint) -> Entity {
-        Entity {object, level, position}
+    fn new(bundle: Bundle, level: Idx, position: IPoint) -> Entity {
+        Entity { idx: bundle.idx, components: bundle.components, ai: bundle.ai, level, position }
+    }
+    pub fn idx(&self) -> Idx {
+        self.idx
     }
     pub fn level(&self) -> Idx {
         self.level
@@ -33,11 +136,100 @@ impl Entity {
     pub fn position(&self) -> IPoint {
         self.position
     }
-    pub fn object(&self) -> &Object {
-        self.object.deref()
+    pub fn name(&self) -> &str {
+        &self.components.name
+    }
+    pub fn components(&self) -> &Components {
+        &self.components
+    }
+    // blockers, opacity and rendering are indexed by the level, so only the rest can change in place
+    pub fn health_mut(&mut self) -> Option<&mut Health> {
+        self.components.health.as_mut()
+    }
+    pub fn inventory_mut(&mut self) -> Option<&mut Inventory> {
+        self.components.inventory.as_mut()
+    }
+    pub fn ai(&self) -> Option<&Object> {
+        self.ai.as_ref().map(|ai| ai.deref())
+    }
+    pub fn ai_mut(&mut self) -> Option<&mut Object> {
+        match self.ai {
+            Some(ref mut ai) => Some(ai.deref_mut()),
+            None => None,
+        }
+    }
+    pub fn as_player(&mut self) -> Option<&mut Player> {
+        self.ai_mut().and_then(|ai| ai.as_player())
+    }
+    pub fn into_bundle(self) -> Bundle {
+        Bundle { idx: self.idx, components: self.components, ai: self.ai }
+    }
+    pub fn kinds(&self) -> Vec<ComponentKind> {
+        let mut kinds = self.components.kinds();
+        if self.ai.is_some() {
+            kinds.push(ComponentKind::Ai);
+        }
+        kinds
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.components.health.map_or(false, |h| h.is_alive())
     }
-    pub fn object_mut(&mut self) -> &mut Object {
-        self.object.deref_mut()
+    pub fn is_active(&self) -> bool {
+        self.ai().map_or(false, |ai| ai.is_active())
+    }
+    // dead entities and those without an active ai never get a turn
+    pub fn get_cooldown(&self) -> i64 {
+        match self.ai() {
+            Some(ai) if self.components.health.map_or(true, |h| h.is_alive()) => ai.get_cooldown(),
+            _ => i64::max_value(),
+        }
+    }
+    // lets the ai's timers and status effects run down, the health they change stays within bounds;
+    // returns whether they took the last of it
+    pub fn lapse_time(&mut self, interval: i64) -> bool {
+        let change = match self.ai.as_mut() {
+            None => return false,
+            Some(ai) => ai.lapse_time(interval),
+        };
+        match self.components.health.as_mut().filter(|h| h.is_alive()) {
+            None => false,
+            Some(health) => {
+                health.health = (health.health + change).max(0).min(health.max_health);
+                !health.is_alive()
+            }
+        }
+    }
+    pub fn public_stats(&self) -> Option<PublicStats> {
+        let health = self.components.health?;
+        let abilities = self.ai()?.abilities()?;
+        Some(PublicStats {
+            health: health.health,
+            max_health: health.max_health,
+            sight_range: abilities.sight_range,
+            walk_speed: abilities.walk_speed,
+            attack_speed: abilities.attack_speed,
+            wait_speed: abilities.wait_speed,
+        })
+    }
+}
+
+// how a level is simulated while no player is on it
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SimulationPolicy {
+    // every entity acts as if a player was there
+    Full,
+    // nothing happens, entities carry on where they stopped once a player comes back
+    Frozen,
+    // like frozen, but once a player comes back the time they missed passes at once:
+    // cooldowns and status effects run down, nobody acts
+    CatchUp,
+}
+
+impl Default for SimulationPolicy {
+    fn default() -> SimulationPolicy {
+        SimulationPolicy::Full
     }
 }
 
@@ -47,7 +239,15 @@ impl Level {
         for pos in size.zrange().iter() {
             tiles.insert(pos, Vec::new());
         }
-        Level {idx, size, tiles, positions: HashMap::new()}
+        Level {
+            idx, size, tiles,
+            positions: HashMap::new(),
+            added: Vec::new(),
+            policy: SimulationPolicy::Full,
+            paused: false,
+            sleeping: Vec::new(),
+            index: ComponentIndex::default(),
+        }
     }
 
     pub fn idx(&self) -> Idx {
@@ -63,32 +263,104 @@ impl Level {
         &self.positions
     }
 
-    pub fn add_entity(&mut self, object: Box<Object>, position: IPoint) -> &mut Entity {
-        let idx = object.get_idx();
+    pub fn add_entity<B: Into<Bundle>>(&mut self, bundle: B, position: IPoint) -> GameResult<&mut Entity> {
+        let bundle = bundle.into();
+        let idx = bundle.idx;
+        let tile = match self.tiles.get_mut(&position) {
+            None => return Err(GameError::OutsideLevel { level: self.idx, position }),
+            Some(t) => t,
+        };
         match self.positions.entry(idx) {
-            Entry::Occupied(_) => panic!(),
+            Entry::Occupied(_) => return Err(GameError::DuplicateEntity { idx }),
             Entry::Vacant(e) => e.insert(position),
         };
 
-        let entity = Entity::new(object, self.idx, position);
-        let tile = self.tiles.get_mut(&position).unwrap();
+        let entity = Entity::new(bundle, self.idx, position);
+        if entity.is_active() {
+            self.added.push(idx);
+        }
+        self.index.insert(&entity);
         tile.push(entity);
-        tile.last_mut().unwrap()
+        Ok(tile.last_mut().unwrap())
     }
     pub fn remove_entity(&mut self, idx: Idx) -> Option<Entity> {
-        self.positions.remove(&idx)
-            .map(|pos| {
-                let tile = self.tiles.get_mut(&pos).unwrap();
-                let index = tile.iter().position(|e| e.object.get_idx() == idx).unwrap();
-                tile.remove(index)
-            })
-    }
-    pub fn move_entity(&mut self, idx: Idx, new_position: IPoint) -> Option<&mut Entity> {
-        let removed = self.remove_entity(idx);
-        match removed {
-            None => None,
-            Some(entity) => Some(self.add_entity(entity.object, new_position)),
+        let pos = self.positions.remove(&idx)?;
+        let tile = self.tiles.get_mut(&pos)?;
+        let index = tile.iter().position(|e| e.idx == idx)?;
+        let entity = tile.remove(index);
+        self.index.remove(&entity);
+        Some(entity)
+    }
+    pub fn move_entity(&mut self, idx: Idx, new_position: IPoint) -> GameResult<&mut Entity> {
+        if !self.tiles.contains_key(&new_position) {
+            return Err(GameError::OutsideLevel { level: self.idx, position: new_position });
+        }
+        match self.remove_entity(idx) {
+            None => Err(GameError::MissingEntity { idx }),
+            Some(entity) => self.add_entity(entity.into_bundle(), new_position),
+        }
+    }
+
+    pub fn take_added(&mut self) -> Vec<Idx> {
+        self.added.drain(..).collect()
+    }
+    pub fn added(&self) -> &[Idx] {
+        &self.added
+    }
+    // as if every active entity had just been added, for a world rebuilding its schedule
+    pub fn add_all(&mut self) {
+        self.added = self.active();
+    }
+    fn active(&self) -> Vec<Idx> {
+        self.query(ComponentKind::Ai)
+            .filter(|e| e.is_active())
+            .map(|e| e.idx)
+            .collect()
+    }
+
+    pub fn policy(&self) -> SimulationPolicy {
+        self.policy
+    }
+    pub fn set_policy(&mut self, policy: SimulationPolicy) {
+        self.policy = policy;
+    }
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+    pub fn sleeping(&self) -> impl Iterator<Item=Idx> + '_ {
+        self.sleeping.iter().map(|s| s.0)
+    }
+    // puts the entities to sleep, they are left out of the schedule until the level resumes
+    pub fn sleep(&mut self, entities: &[Idx], time: i64) {
+        for idx in entities {
+            if self.sleeping.iter().all(|s| s.0 != *idx) {
+                self.sleeping.push((*idx, time));
+            }
+        }
+    }
+    // returns the active entities, which the world takes out of its schedule
+    pub fn pause(&mut self, time: i64) -> Vec<Idx> {
+        self.paused = true;
+        self.added.clear();
+        let active = self.active();
+        self.sleep(&active, time);
+        active
+    }
+    // wakes the sleeping entities that are still here and returns them, after catching up if the policy says so
+    pub fn resume(&mut self, time: i64) -> Vec<Idx> {
+        self.paused = false;
+        let mut awake = Vec::new();
+        for (idx, since) in mem::replace(&mut self.sleeping, Vec::new()) {
+            let catch_up = self.policy == SimulationPolicy::CatchUp;
+            if let Some(entity) = self.get_mut_entity(idx) {
+                if catch_up && time > since {
+                    trace!("entity {} catches up {}", idx, time - since);
+                    entity.lapse_time(time - since);
+                }
+                awake.push(idx);
+            }
         }
+        awake
     }
 
     pub fn get_tile(&self, position: IPoint) -> Option<&Vec<Entity>> {
@@ -111,44 +383,52 @@ impl Level {
     pub fn get_entity(&self, idx: Idx) -> Option<&Entity> {
         self.get_position(idx)
             .and_then(|pos| self.tiles.get(&pos))
-            .and_then(|tile| tile.iter().find(|e| e.object.get_idx() == idx))
+            .and_then(|tile| tile.iter().find(|e| e.idx == idx))
     }
     pub fn get_mut_entity<'a>(&'a mut self, idx: Idx) -> Option<&'a mut Entity> {
         let tile = match self.positions.get(&idx) {
             None => None,
             Some(p) => self.tiles.get_mut(p),
         };
-        tile.and_then(|t| t.iter_mut().find(|e| e.object.get_idx() == idx))
+        tile.and_then(|t| t.iter_mut().find(|e| e.idx == idx))
+    }
+    // entities with a kind of component, in idx order
+    pub fn query<'a>(&'a self, kind: ComponentKind) -> impl Iterator<Item=&'a Entity> + 'a {
+        self.index.kinds.get(&kind).into_iter()
+            .flat_map(|set| set.iter())
+            .filter_map(move |idx| self.get_entity(*idx))
     }
 
     pub fn visible_points(&self, pos: IPoint, range: f32) -> HashSet<IPoint> {
         let transparent: HashSet<IPoint> =
-            self.tiles().iter()
-                .filter(|(k, vec)| pos.dist(**k) <= range && Level::is_transparent(vec))
-                .map(|(k, _vec)| *k)
+            self.tiles().keys()
+                .filter(|k| pos.dist(**k) <= range && !self.index.opaque.contains_key(k))
+                .map(|k| *k)
                 .collect();
         visibility_set(&transparent, self.size(), pos, range)
     }
 
     pub fn build_mem_pixel(tile: &Vec<Entity>) -> Pixel {
         tile.iter()
-            .filter(|e| e.object().is_environment())
-            .max_by_key(|e| e.object().get_ordinal())
-            .map_or(Pixel::empty(), |o| o.object().get_pixel().gray())
+            .filter_map(|e| e.components.render)
+            .filter(|r| r.environment)
+            .max_by_key(|r| r.ordinal)
+            .map_or(Pixel::empty(), |r| r.pixel.gray())
     }
     pub fn build_pixel(tile: &Vec<Entity>) -> Pixel {
         tile.iter()
-            .max_by_key(|e| e.object().get_ordinal())
-            .map_or(Pixel::empty(), |o| o.object().get_pixel())
+            .filter_map(|e| e.components.render)
+            .max_by_key(|r| r.ordinal)
+            .map_or(Pixel::empty(), |r| r.pixel)
     }
-    pub fn is_transparent(tile: &Vec<Entity>) -> bool {
-        tile.iter()
-            .find(|e| e.object().is_opaque())
-            .is_none()
+    // tiles outside the level are neither transparent nor walkable
+    pub fn is_transparent(&self, position: IPoint) -> bool {
+        self.tiles.contains_key(&position) && !self.index.opaque.contains_key(&position)
     }
-    pub fn is_blocking(tile: &Vec<Entity>) -> bool {
-        tile.iter()
-            .find(|e| e.object().is_blocking())
-            .is_some()
+    pub fn is_blocking(&self, position: IPoint) -> bool {
+        !self.tiles.contains_key(&position) || self.index.blocking.contains_key(&position)
+    }
+    pub fn get_blocker(&self, position: IPoint) -> Option<&Entity> {
+        self.get_tile(position)?.iter().find(|e| e.components.blocker)
     }
 }
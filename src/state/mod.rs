@@ -2,3 +2,11 @@ pub mod level;
 pub mod object;
 pub mod world;
 pub mod context;
+pub mod save;
+pub mod event;
+pub mod scheduler;
+pub mod status;
+pub mod timer;
+pub mod component;
+pub mod faction;
+pub mod stats;
@@ -1,11 +1,14 @@
 use state::context::Action;
 use state::context::Context;
 use state::context::Effects;
+use state::context::Outcome;
 use objects::player::Player;
+use state::save::ObjectData;
+use state::status::{StatusEffect, StatusEffects, StatusKind};
 
 pub type Idx = u32;
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 pub struct Pixel (pub Icon, pub Color);
 impl Pixel {
     pub fn empty() -> Pixel {
@@ -16,10 +19,10 @@ impl Pixel {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 pub struct Color (pub u8, pub u8, pub u8);
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 pub enum Icon {
     Wall,
     Player,
@@ -28,21 +31,49 @@ pub enum Icon {
     Empty
 }
 
+// what other entities may learn about an entity by looking at it, see Entity::public_stats
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct PublicStats {
+    pub health: i32,
+    pub max_health: i32,
+    pub sight_range: f32,
+    pub walk_speed: i64,
+    pub attack_speed: i64,
+    pub wait_speed: i64,
+}
+
+// the part of the public stats an ai knows about, health is a component
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Abilities {
+    pub sight_range: f32,
+    pub walk_speed: i64,
+    pub attack_speed: i64,
+    pub wait_speed: i64,
+}
+
+// the ai component: what an entity does, the entity's other components hold what it is
 pub trait Object {
     fn get_idx(&self) -> Idx;
-    fn get_pixel(&self) -> Pixel;
-    fn get_ordinal(&self) -> i32;
-    fn is_environment(&self) -> bool;
-    fn is_blocking(&self) -> bool;
-    fn is_opaque(&self) -> bool;
-    fn name(&self) -> &str;
+    fn save(&self) -> ObjectData;
+
+    fn abilities(&self) -> Option<Abilities> { None }
+    fn status(&self) -> Option<&StatusEffects> { None }
+    // returns whether the object can be affected at all
+    fn add_status(&mut self, _effect: StatusEffect) -> bool { false }
+    fn remove_status(&mut self, _kind: StatusKind) -> bool { false }
 
     fn is_active(&self) -> bool { false }
     fn get_cooldown(&self) -> i64 { i64::max_value() }
-    fn lapse_time(&mut self, _interval: i64) { }
+    // returns the change in health, from status effects
+    fn lapse_time(&mut self, _interval: i64) -> i32 { 0 }
     fn update(&self, _context: Context, _effects: &mut Effects) { }
     fn plan_action(&self, _context: Context, _effects: &mut Effects) { }
+    // the world applies damage to the health component, this is for the costs of the action
     fn execute_action(&mut self, _effects: &mut Effects, _action: &Action) { }
+    // called for every living active entity before an action resolves and after it did,
+    // actions pushed to effects.reactions run right away, see World::resolve
+    fn interrupt(&self, _context: Context, _action: &Action, _depth: usize, _effects: &mut Effects) { }
+    fn react(&self, _context: Context, _action: &Action, _outcome: &Outcome, _depth: usize, _effects: &mut Effects) { }
 
     fn as_player(&mut self) -> Option<&mut Player> { None }
 }
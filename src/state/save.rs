@@ -0,0 +1,39 @@
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use state::object::Object;
+use objects::player::Player;
+use objects::character::Character;
+
+// version of the save format, bump it whenever old saves can no longer be read as they are;
+// new fields should come with #[serde(default)] and new object types with a new variant,
+// so both kinds of changes keep older saves loadable without a bump
+// 2: the single player became a list of players
+// 3: entities are made of components, walls and floors lost their objects
+pub const SAVE_VERSION: u32 = 3;
+
+// the kinds of ai an entity can have
+#[derive(Serialize, Deserialize)]
+pub enum ObjectData {
+    Player(Player),
+    Character(Character),
+}
+
+impl ObjectData {
+    pub fn load(self) -> Box<Object> {
+        match self {
+            ObjectData::Player(o) => Box::new(o),
+            ObjectData::Character(o) => Box::new(o),
+        }
+    }
+}
+
+impl Serialize for Box<Object> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.save().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Box<Object> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        ObjectData::deserialize(deserializer).map(ObjectData::load)
+    }
+}
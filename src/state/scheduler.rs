@@ -0,0 +1,57 @@
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use state::object::Idx;
+
+// active entities, and the time each of them is ready to act;
+// the queue is ordered by (ready time, idx), so ties go to the lowest idx
+#[derive(Default)]
+pub struct Scheduler {
+    active: BTreeSet<Idx>,
+    ready: HashMap<Idx, i64>,
+    queue: BTreeSet<(i64, Idx)>,
+    built: bool,
+}
+
+impl Scheduler {
+    pub fn is_built(&self) -> bool {
+        self.built
+    }
+    pub fn set_built(&mut self) {
+        self.built = true;
+    }
+    pub fn clear(&mut self) {
+        *self = Scheduler::default();
+    }
+
+    pub fn insert(&mut self, idx: Idx) {
+        self.active.insert(idx);
+    }
+    pub fn remove(&mut self, idx: Idx) {
+        self.active.remove(&idx);
+        self.set_ready(idx, None);
+    }
+    // None takes the entity out of the queue, it stays active but never gets a turn
+    pub fn set_ready(&mut self, idx: Idx, time: Option<i64>) {
+        if let Some(old) = self.ready.remove(&idx) {
+            self.queue.remove(&(old, idx));
+        }
+        if let Some(time) = time {
+            self.ready.insert(idx, time);
+            self.queue.insert((time, idx));
+        }
+    }
+
+    pub fn contains(&self, idx: Idx) -> bool {
+        self.active.contains(&idx)
+    }
+
+    pub fn first(&self) -> Option<(i64, Idx)> {
+        self.queue.iter().next().map(|t| *t)
+    }
+    pub fn active(&self) -> Vec<Idx> {
+        self.active.iter().cloned().collect()
+    }
+    pub fn len(&self) -> usize {
+        self.active.len()
+    }
+}
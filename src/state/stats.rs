@@ -0,0 +1,160 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use rand::Rng;
+
+// the most dice and sides a roll may have, so rolls stay far from overflowing
+pub const MAX_DICE: u32 = 100;
+pub const MAX_SIDES: u32 = 1000;
+
+// count dice of sides each plus bonus, written like 2d6+1; a plain number never rolls
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dice {
+    pub count: u32,
+    pub sides: u32,
+    pub bonus: i32,
+}
+
+impl Dice {
+    pub fn flat(bonus: i32) -> Dice {
+        Dice { count: 0, sides: 0, bonus }
+    }
+    // wider than a roll, so dice that could overflow can be checked
+    pub fn min(&self) -> i64 {
+        if self.sides == 0 { self.bonus as i64 } else { self.count as i64 + self.bonus as i64 }
+    }
+    pub fn max(&self) -> i64 {
+        self.count as i64 * self.sides as i64 + self.bonus as i64
+    }
+    // rolling dice that do not pass can overflow or panic
+    pub fn validate(&self) -> Result<(), String> {
+        if self.count > MAX_DICE {
+            return Err(format!("can not have more than {} dice, got {}", MAX_DICE, self));
+        }
+        if self.sides > MAX_SIDES {
+            return Err(format!("can not have more than {} sides, got {}", MAX_SIDES, self));
+        }
+        if self.max() > i32::max_value() as i64 {
+            return Err(format!("can not roll over {}, got {}", i32::max_value(), self));
+        }
+        Ok(())
+    }
+    // draws nothing from rand for flat dice
+    pub fn roll<R: Rng>(&self, rand: &mut R) -> i32 {
+        let mut total = self.bonus;
+        if self.sides > 0 {
+            for _ in 0..self.count {
+                total += rand.gen_range(1, self.sides as i32 + 1);
+            }
+        }
+        total
+    }
+}
+
+impl Display for Dice {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.count == 0 || self.sides == 0 {
+            return write!(f, "{}", self.bonus);
+        }
+        write!(f, "{}d{}", self.count, self.sides)?;
+        if self.bonus != 0 {
+            write!(f, "{:+}", self.bonus)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Dice {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Dice, String> {
+        let invalid = || format!("{} is not a number or dice like 2d6+1", s);
+        let s = s.trim();
+        let d = match s.find('d') {
+            None => return s.parse().map(Dice::flat).map_err(|_| invalid()),
+            Some(d) => d,
+        };
+        let (count, rest) = (&s[..d], &s[d + 1..]);
+        let sign = rest.find(|c| c == '+' || c == '-').unwrap_or(rest.len());
+        let (sides, bonus) = (&rest[..sign], &rest[sign..]);
+        Ok(Dice {
+            count: if count.is_empty() { 1 } else { count.parse().map_err(|_| invalid())? },
+            sides: sides.parse().map_err(|_| invalid())?,
+            bonus: if bonus.is_empty() { 0 } else { bonus.parse().map_err(|_| invalid())? },
+        })
+    }
+}
+
+impl Serialize for Dice {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DiceData {
+    Flat(i32),
+    Notation(String),
+}
+
+impl<'de> Deserialize<'de> for Dice {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Dice, D::Error> {
+        match DiceData::deserialize(deserializer)? {
+            DiceData::Flat(bonus) => Ok(Dice::flat(bonus)),
+            DiceData::Notation(s) => s.parse().map_err(::serde::de::Error::custom),
+        }
+    }
+}
+
+// the fighting stats of a creature, its health is a component of its own;
+// chances are percentages, the default always hits for 1
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct Combat {
+    // added to the damage dealt
+    pub attack: i32,
+    // taken from the damage received
+    pub defense: i32,
+    // chance to hit before the target's evasion is taken off
+    pub accuracy: i32,
+    pub evasion: i32,
+    // chance of a hit to be critical, rolling the damage twice
+    pub critical: i32,
+    #[serde(deserialize_with = "valid_damage")]
+    pub damage: Dice,
+}
+
+// saves are checked like templates, as they could bring dice that panic when rolled
+fn valid_damage<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Dice, D::Error> {
+    let dice = Dice::deserialize(deserializer)?;
+    dice.validate().map(|_| dice).map_err(|message| ::serde::de::Error::custom(format!("damage {}", message)))
+}
+
+impl Default for Combat {
+    fn default() -> Combat {
+        Combat { attack: 0, defense: 0, accuracy: 100, evasion: 0, critical: 0, damage: Dice::flat(1) }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "roll", rename_all = "lowercase")]
+pub enum Roll {
+    Miss,
+    Hit { damage: i32, critical: bool },
+}
+
+// sure hits and critical chances of 0 draw nothing from rand
+pub fn roll_attack<R: Rng>(attacker: &Combat, defender: &Combat, rand: &mut R) -> Roll {
+    let chance = (attacker.accuracy - defender.evasion).max(0).min(100);
+    if chance < 100 && rand.gen_range(0, 100) >= chance {
+        return Roll::Miss;
+    }
+    let critical = attacker.critical > 0 && rand.gen_range(0, 100) < attacker.critical;
+    let mut damage = attacker.damage.roll(rand);
+    if critical {
+        damage = damage.saturating_add(attacker.damage.roll(rand));
+    }
+    let damage = damage.saturating_add(attacker.attack).saturating_sub(defender.defense);
+    Roll::Hit { damage: damage.max(0), critical }
+}
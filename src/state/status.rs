@@ -0,0 +1,101 @@
+// time between two ticks of poison or regeneration
+pub const TICK: i64 = 10;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusKind {
+    Haste,
+    Slow,
+    Poison,
+    Regeneration,
+    Stun,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    // time left until the effect expires
+    pub remaining: i64,
+    // percent for haste and slow, health per tick for poison and regeneration, unused by stun
+    pub strength: i32,
+    #[serde(default)]
+    elapsed: i64,
+}
+
+impl StatusEffect {
+    pub fn new(kind: StatusKind, duration: i64, strength: i32) -> StatusEffect {
+        StatusEffect { kind, remaining: duration, strength, elapsed: 0 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct StatusEffects {
+    effects: Vec<StatusEffect>,
+}
+
+impl StatusEffects {
+    pub fn list(&self) -> &[StatusEffect] {
+        &self.effects
+    }
+
+    // poison stacks its strength, other kinds keep the stronger and the longer of both
+    pub fn add(&mut self, effect: StatusEffect) {
+        if effect.remaining <= 0 {
+            return;
+        }
+        match self.effects.iter_mut().find(|e| e.kind == effect.kind) {
+            None => self.effects.push(effect),
+            Some(e) => {
+                e.strength = match effect.kind {
+                    StatusKind::Poison => e.strength + effect.strength,
+                    _ => e.strength.max(effect.strength),
+                };
+                e.remaining = e.remaining.max(effect.remaining);
+            }
+        }
+    }
+
+    // health change from poison and regeneration ticks during the interval, drops expired effects
+    pub fn lapse_time(&mut self, interval: i64) -> i32 {
+        let mut health = 0;
+        for e in self.effects.iter_mut() {
+            let step = interval.min(e.remaining);
+            let ticks = ((e.elapsed + step) / TICK - e.elapsed / TICK) as i32;
+            match e.kind {
+                StatusKind::Poison => health -= ticks * e.strength,
+                StatusKind::Regeneration => health += ticks * e.strength,
+                _ => (),
+            }
+            e.elapsed += step;
+            e.remaining -= step;
+        }
+        self.effects.retain(|e| e.remaining > 0);
+        health
+    }
+
+    // cost of an action after haste and slow, actions that cost anything never become free
+    pub fn action_cost(&self, base: i64) -> i64 {
+        let cost = self.effects.iter().fold(base, |cost, e| match e.kind {
+            StatusKind::Haste => cost * 100 / (100 + e.strength.max(0) as i64),
+            StatusKind::Slow => cost * (100 + e.strength.max(0) as i64) / 100,
+            _ => cost,
+        });
+        cost.max(base.min(1))
+    }
+
+    // returns whether there was an effect of the kind
+    pub fn remove(&mut self, kind: StatusKind) -> bool {
+        let count = self.effects.len();
+        self.effects.retain(|e| e.kind != kind);
+        self.effects.len() != count
+    }
+
+    // time left until a stun wears off
+    pub fn stunned(&self) -> i64 {
+        self.effects.iter()
+            .filter(|e| e.kind == StatusKind::Stun)
+            .map(|e| e.remaining)
+            .max()
+            .unwrap_or(0)
+    }
+}
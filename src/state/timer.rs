@@ -0,0 +1,33 @@
+use state::object::Idx;
+use state::status::StatusKind;
+
+// something the world does at a set time, see World::schedule_event
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WorldEvent {
+    // a message for every player
+    Announce { message: String },
+    // ends a status effect before it runs out
+    ExpireStatus { idx: Idx, kind: StatusKind },
+    // a creature from a template, on a random free tile of the level
+    SpawnMonster {
+        level: Idx,
+        #[serde(default = "default_monster")]
+        template: String,
+    },
+}
+
+// timers saved before templates existed
+fn default_monster() -> String {
+    "monster".to_string()
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Timer {
+    pub id: u32,
+    // world time the event fires at
+    pub at: i64,
+    // fires again this long after each time, if set
+    pub every: Option<i64>,
+    pub event: WorldEvent,
+}
@@ -1,54 +1,242 @@
 use std::collections::HashMap;
 use std::collections::hash_map::Entry;
-use state::level::Level;
+use std::collections::BTreeSet;
+use state::level::{Level, SimulationPolicy};
 use state::object::Idx;
 use std::cell::Cell;
 use state::level::Entity;
+use state::object::Object;
 use state::context::Context;
-use rand::XorShiftRng;
-use state::context::Action;
+use utils::xorshift::XorShift;
+use state::context::{Action, Outcome};
 use rand::SeedableRng;
 use rand::Rng;
 use state::context::Effects;
-use print;
+use error::{GameError, GameResult};
+use state::event::Event;
+use state::scheduler::Scheduler;
+use utils::ipoint::IPoint;
+use state::status::StatusEffect;
+use utils::fnv::Fnv;
+use std::hash::Hasher;
+use serde_json;
+use player::command::Direction;
+use state::timer::{Timer, WorldEvent};
+use design::templates::Templates;
+use state::faction::{Factions, Relation};
+use state::stats::{roll_attack, Roll};
 
+// reactions to reactions are followed this deep
+pub const MAX_REACTION_DEPTH: usize = 3;
+
+#[derive(Serialize, Deserialize)]
 pub struct World {
-    rand: XorShiftRng,
+    rand: XorShift,
     levels: HashMap<Idx, Level>,
     next_id: Cell<Idx>,
     time: i64,
+    // rebuilt from the levels on first use after loading
+    #[serde(skip)]
+    schedule: Scheduler,
+    // ordered by (at, id)
+    #[serde(default)]
+    timers: Vec<Timer>,
+    #[serde(default)]
+    next_timer: u32,
+    // effects of timers that fired in lapse_time, until the game takes them
+    #[serde(skip)]
+    fired: Vec<Effects>,
+    // creatures poison killed in lapse_time, until the game takes them
+    #[serde(skip)]
+    poisoned: Vec<Idx>,
+    // the builtin ones until the game sets those of its config
+    #[serde(skip)]
+    templates: Templates,
+    // changes while playing, unlike the templates
+    #[serde(default)]
+    factions: Factions,
 }
 
 impl World {
-    pub fn new() -> World {
+    pub fn new(seed: [u32; 4]) -> World {
         World {
-            rand: XorShiftRng::from_seed([1, 2, 3, 4]),
+            rand: XorShift::from_seed(seed),
             levels: HashMap::new(),
             next_id: Cell::new(0),
             time: 0,
+            schedule: Scheduler::default(),
+            timers: Vec::new(),
+            next_timer: 0,
+            fired: Vec::new(),
+            poisoned: Vec::new(),
+            templates: Templates::default(),
+            factions: Factions::default(),
         }
     }
 
+    pub fn time(&self) -> i64 {
+        self.time
+    }
+
     pub fn levels(&self) -> &HashMap<Idx, Level> {
         &self.levels
     }
 
+    // entities the scheduler tracks and those asleep on paused levels, dead ones included
+    pub fn active_entities(&self) -> Vec<Idx> {
+        let mut active = self.schedule.active();
+        for lvl in self.levels.values() {
+            active.extend(lvl.sleeping());
+        }
+        active.sort();
+        active.dedup();
+        active
+    }
+
+    pub fn get_level(&self, idx: Idx) -> Option<&Level> {
+        self.levels.get(&idx)
+    }
+    pub fn get_mut_level(&mut self, idx: Idx) -> Option<&mut Level> {
+        self.levels.get_mut(&idx)
+    }
+
+    // covers the clock, the random generator, the timers and every entity with its position and state;
+    // levels and entities go in idx order, so it does not depend on hash order
+    pub fn state_hash(&self) -> GameResult<u64> {
+        let json = |e: serde_json::Result<String>| e.map_err(|e| GameError::InvalidJson { message: e.to_string() });
+        let mut hasher = Fnv::default();
+        hasher.write_i64(self.time);
+        hasher.write_u32(self.next_id.get());
+        hasher.write(json(serde_json::to_string(&self.rand))?.as_bytes());
+        hasher.write(json(serde_json::to_string(&self.timers))?.as_bytes());
+        hasher.write(json(serde_json::to_string(&self.factions))?.as_bytes());
+
+        let mut levels: Vec<&Level> = self.levels.values().collect();
+        levels.sort_by_key(|l| l.idx());
+        for level in levels {
+            hasher.write_u32(level.idx());
+            hasher.write_i32(level.size().x);
+            hasher.write_i32(level.size().y);
+            let mut entities: Vec<&Entity> = level.get_entities().collect();
+            entities.sort_by_key(|e| e.idx());
+            for entity in entities {
+                hasher.write_u32(entity.idx());
+                hasher.write_i32(entity.position().x);
+                hasher.write_i32(entity.position().y);
+                // the other components never change
+                if let Some(health) = entity.components().health {
+                    hasher.write_i32(health.health);
+                    hasher.write_i32(health.max_health);
+                }
+                if let Some(inventory) = entity.components().inventory.as_ref() {
+                    hasher.write(json(serde_json::to_string(inventory))?.as_bytes());
+                }
+                if let Some(ai) = entity.ai() {
+                    hasher.write(json(serde_json::to_string(&ai.save()))?.as_bytes());
+                }
+            }
+        }
+        Ok(hasher.finish())
+    }
+
     pub fn next_id(&self) -> Idx {
         let next = self.next_id.get();
         self.next_id.set(next + 1);
         return next;
     }
 
-    pub fn add_level(&mut self, level: Level) -> &mut Level {
+    pub fn add_level(&mut self, level: Level) -> GameResult<&mut Level> {
         match self.levels.entry(level.idx()) {
-            Entry::Occupied(_) => panic!(),
-            Entry::Vacant(e) => e.insert(level),
+            Entry::Occupied(e) => Err(GameError::DuplicateLevel { idx: *e.key() }),
+            Entry::Vacant(e) => Ok(e.insert(level)),
         }
     }
     pub fn remove_lvl(&mut self, idx: Idx) -> Option<Level> {
         self.levels.remove(&idx)
     }
 
+    // moves an entity to a position on any level, it keeps its idx and its place in the schedule
+    pub fn transfer_entity(&mut self, idx: Idx, level: Idx, position: IPoint) -> GameResult<&mut Entity> {
+        match self.levels.get(&level) {
+            None => return Err(GameError::MissingLevel { idx: level }),
+            Some(lvl) if lvl.get_tile(position).is_none() =>
+                return Err(GameError::OutsideLevel { level, position }),
+            Some(_) => (),
+        }
+        let entity = self.get_mut_entity_level(idx)
+            .and_then(|lvl| lvl.remove_entity(idx))
+            .ok_or(GameError::MissingEntity { idx })?;
+        let target = self.levels.get_mut(&level).ok_or(GameError::MissingLevel { idx: level })?;
+        target.add_entity(entity.into_bundle(), position)
+    }
+
+    // returns whether the entity can carry status effects at all
+    pub fn apply_status(&mut self, idx: Idx, effect: StatusEffect) -> GameResult<bool> {
+        let entity = self.get_mut_entity(idx).ok_or(GameError::MissingEntity { idx })?;
+        let applied = entity.ai_mut().map_or(false, |ai| ai.add_status(effect));
+        // a stun changes when the entity is ready
+        self.reschedule(idx);
+        Ok(applied)
+    }
+
+    // at is absolute world time, past times fire on the next lapse; returns the timer id
+    pub fn schedule_event(&mut self, at: i64, every: Option<i64>, event: WorldEvent) -> GameResult<u32> {
+        if let Some(every) = every.filter(|e| *e <= 0) {
+            return Err(GameError::InvalidTimer { message: format!("every must be positive, got {}", every) });
+        }
+        let id = self.next_timer;
+        self.next_timer += 1;
+        let at = at.max(self.time);
+        self.insert_timer(Timer { id, at, every, event });
+        Ok(id)
+    }
+    // returns whether the timer was still pending
+    pub fn cancel_event(&mut self, id: u32) -> bool {
+        let count = self.timers.len();
+        self.timers.retain(|t| t.id != id);
+        self.timers.len() != count
+    }
+    pub fn timers(&self) -> &[Timer] {
+        &self.timers
+    }
+    pub fn templates(&self) -> &Templates {
+        &self.templates
+    }
+    pub fn set_templates(&mut self, templates: Templates) {
+        self.templates = templates;
+    }
+    pub fn factions(&self) -> &Factions {
+        &self.factions
+    }
+    pub fn set_factions(&mut self, factions: Factions) {
+        self.factions = factions;
+    }
+    pub fn set_relation(&mut self, a: &str, b: &str, relation: Relation) {
+        self.factions.set(a, b, relation);
+    }
+    pub fn faction(&self, idx: Idx) -> Option<&str> {
+        self.get_entity(idx).and_then(|e| e.components().faction.as_ref()).map(|f| f.as_str())
+    }
+    // entities without a faction are neutral to everything
+    pub fn relation(&self, a: Idx, b: Idx) -> Relation {
+        match (self.faction(a), self.faction(b)) {
+            (Some(a), Some(b)) => self.factions.relation(a, b),
+            _ => Relation::Neutral,
+        }
+    }
+    pub fn take_fired(&mut self) -> Vec<Effects> {
+        self.fired.drain(..).collect()
+    }
+    pub fn take_poisoned(&mut self) -> Vec<Idx> {
+        self.poisoned.drain(..).collect()
+    }
+    fn insert_timer(&mut self, timer: Timer) {
+        let index = self.timers.iter()
+            .position(|t| (t.at, t.id) > (timer.at, timer.id))
+            .unwrap_or(self.timers.len());
+        self.timers.insert(index, timer);
+    }
+
     pub fn get_mut_entity(&mut self, idx: Idx) -> Option<&mut Entity> {
         self.levels.iter_mut()
             .map(|(_, lvl)| lvl.get_mut_entity(idx))
@@ -72,38 +260,195 @@ impl World {
             .find(|(_, lvl)| lvl.get_entity(idx).is_some())
             .map(|t| t.1)
     }
+    // whether the tiles left and right of the entity, seen facing dir, can be walked on
+    pub fn open_sides(&self, idx: Idx, dir: Direction) -> GameResult<(bool, bool)> {
+        let level = self.get_entity_level(idx).ok_or(GameError::MissingEntity { idx })?;
+        let position = level.get_position(idx).ok_or(GameError::MissingEntity { idx })?;
+        let IPoint { x, y } = dir.offset();
+        let open = |side: IPoint| !level.is_blocking(position + side);
+        Ok((open(IPoint { x: y, y: -x }), open(IPoint { x: -y, y: x })))
+    }
+
+    // picks up entities added to the levels since the last call, and pauses or resumes
+    // levels as players leave and enter them
+    fn sync_schedule(&mut self) {
+        if !self.schedule.is_built() {
+            self.schedule.clear();
+            for (_, lvl) in self.levels.iter_mut() {
+                lvl.add_all();
+            }
+            self.schedule.set_built();
+        }
+        let occupied = self.occupied_levels();
+        let time = self.time;
+        let (mut added, mut removed) = (Vec::new(), Vec::new());
+        for (idx, lvl) in self.levels.iter_mut() {
+            let running = lvl.policy() == SimulationPolicy::Full || occupied.contains(idx);
+            if running && lvl.is_paused() {
+                debug!("resuming level {} at {}", idx, time);
+                added.extend(lvl.resume(time));
+            } else if !running && !lvl.is_paused() {
+                debug!("pausing level {} at {}", idx, time);
+                removed.extend(lvl.pause(time));
+            }
+            let fresh = lvl.take_added();
+            if lvl.is_paused() {
+                lvl.sleep(&fresh, time);
+                removed.extend(fresh);
+            } else {
+                added.extend(fresh);
+            }
+        }
+        for idx in removed {
+            self.schedule.remove(idx);
+        }
+        for idx in added {
+            self.schedule.insert(idx);
+            self.reschedule(idx);
+        }
+    }
+
+    // levels with a player on them; players are scheduled or just added, as levels with one never sleep
+    fn occupied_levels(&mut self) -> BTreeSet<Idx> {
+        let mut occupied = BTreeSet::new();
+        if self.levels.values().all(|l| l.policy() == SimulationPolicy::Full) {
+            return occupied;
+        }
+        let mut candidates = self.schedule.active();
+        for lvl in self.levels.values() {
+            candidates.extend(lvl.added());
+        }
+        for idx in candidates {
+            if self.is_player(idx) {
+                occupied.extend(self.get_entity(idx).map(|e| e.level()));
+            }
+        }
+        occupied
+    }
+
+    pub fn set_policy(&mut self, level: Idx, policy: SimulationPolicy) -> GameResult<()> {
+        self.levels.get_mut(&level).ok_or(GameError::MissingLevel { idx: level })?.set_policy(policy);
+        self.sync_schedule();
+        Ok(())
+    }
+
+    // call after changing the cooldown of an entity outside of lapse_time and invoke_actions
+    pub fn reschedule(&mut self, idx: Idx) {
+        let time = self.time;
+        match self.get_entity(idx) {
+            None => self.schedule.remove(idx),
+            // asleep on a paused level
+            Some(_) if !self.schedule.contains(idx) => (),
+            Some(e) => {
+                let cooldown = e.get_cooldown();
+                let ready = if cooldown == i64::max_value() { None } else { Some(time + cooldown) };
+                self.schedule.set_ready(idx, ready);
+            }
+        }
+    }
+
+    // timers due before the next entity is ready fire on the way, their effects wait in take_fired
+    pub fn lapse_time(&mut self) -> GameResult<&mut Entity> {
+        self.sync_schedule();
+        loop {
+            let ready = loop {
+                let (ready, idx) = self.schedule.first().ok_or(GameError::Stalled)?;
+                if self.get_entity(idx).is_some() {
+                    break ready;
+                }
+                // removed from its level behind the scheduler's back
+                self.schedule.remove(idx);
+            };
+            match self.timers.first().map(|t| t.at).filter(|at| *at <= ready) {
+                None => {
+                    self.advance_to(ready);
+                    break;
+                }
+                Some(at) => {
+                    self.advance_to(at);
+                    self.fire_timers()?;
+                    self.sync_schedule();
+                }
+            }
+        }
 
-    pub fn lapse_time(&mut self) -> Option<&mut Entity> {
-        //print("lapse time".to_string());
-        let interval = self.levels.iter().flat_map(
-            |(_lvl_idx, lvl)| lvl.get_entities().map(
-                |entity| entity.object().get_cooldown()
-            )
-        ).min().unwrap();
+        let (_, idx) = self.schedule.first().ok_or(GameError::Stalled)?;
+        self.get_mut_entity(idx).ok_or(GameError::MissingEntity { idx })
+    }
 
-        self.levels.iter_mut().for_each(
-            |(_, lvl)| lvl.get_mut_entities().for_each(
-                |entity| entity.object_mut().lapse_time(interval)
-            )
-        );
+    fn advance_to(&mut self, time: i64) {
+        let interval = time - self.time;
+        self.time = time;
+        trace!("lapse time by {} to {}", interval, self.time);
+        for idx in self.schedule.active() {
+            // poison is the only status effect that takes health
+            if self.get_mut_entity(idx).map_or(false, |e| e.lapse_time(interval)) {
+                self.poisoned.push(idx);
+            }
+            self.reschedule(idx);
+        }
+    }
 
-        self.time += interval;
+    fn fire_timers(&mut self) -> GameResult<()> {
+        let mut effects = self.build_effects();
+        while self.timers.first().map_or(false, |t| t.at <= self.time) {
+            let timer = self.timers.remove(0);
+            debug!("timer {} fires at {}: {:?}", timer.id, self.time, timer.event);
+            self.fire(&mut effects, &timer.event)?;
+            if let Some(every) = timer.every {
+                self.insert_timer(Timer { at: timer.at + every, ..timer });
+            }
+        }
+        self.fired.push(effects);
+        Ok(())
+    }
 
-        self.levels.iter_mut().flat_map(
-            |(_lvl_idx, lvl)| lvl.get_mut_entities()
-        ).find(|entity| entity.object().get_cooldown() == 0)
+    // events about entities or levels that are gone do nothing
+    fn fire(&mut self, effects: &mut Effects, event: &WorldEvent) -> GameResult<()> {
+        match *event {
+            WorldEvent::Announce { ref message } => effects.messages.push(message.clone()),
+            WorldEvent::ExpireStatus { idx, kind } => {
+                if let Some(ai) = self.get_mut_entity(idx).and_then(|e| e.ai_mut()) {
+                    ai.remove_status(kind);
+                }
+                // a stun changes when the entity is ready
+                self.reschedule(idx);
+            }
+            WorldEvent::SpawnMonster { level, ref template } => {
+                let idx = self.next_id();
+                let lvl = match self.levels.get_mut(&level) {
+                    None => return Ok(()),
+                    Some(lvl) => lvl,
+                };
+                let mut free: Vec<IPoint> = lvl.tiles().iter()
+                    .filter(|(p, tile)| !lvl.is_blocking(**p) && !tile.iter().any(|e| e.is_active()))
+                    .map(|(p, _)| *p)
+                    .collect();
+                if free.is_empty() {
+                    return Ok(());
+                }
+                // tiles come in hash order
+                free.sort_by_key(|p| (p.y, p.x));
+                let position = free[effects.rand.gen_range(0, free.len())];
+                self.templates.spawn(template, lvl, idx, position)?;
+                effects.events.push(Event::Spawned { idx, position });
+            }
+        }
+        Ok(())
     }
 
-    pub fn build_context(&self, entity: &Entity) -> Context {
-        Context {
+    pub fn build_context(&self, entity: &Entity) -> GameResult<Context> {
+        let level = self.levels.get(&entity.level())
+            .ok_or(GameError::MissingLevel { idx: entity.level() })?;
+        Ok(Context {
             world: &self,
-            level: self.levels.get(&entity.level()).unwrap(),
+            level,
             position: entity.position(),
-        }
+        })
     }
 
     pub fn build_effects(&mut self) -> Effects {
-        let rand = XorShiftRng::from_seed([
+        let rand = XorShift::from_seed([
             self.rand.next_u32(),
             self.rand.next_u32(),
             self.rand.next_u32(),
@@ -111,46 +456,171 @@ impl World {
         ]);
         let actions = Vec::new();
         let messages = Vec::new();
-        Effects { rand, actions, messages }
+        let events = Vec::new();
+        let reactions = Vec::new();
+        Effects { rand, actions, messages, events, reactions, outcome: None }
     }
 
-    pub fn invoke_actions(&mut self, entity_idx: Idx) -> Vec<String> {
+    fn is_player(&mut self, idx: Idx) -> bool {
+        self.get_mut_entity(idx).map_or(false, |e| e.as_player().is_some())
+    }
+
+    pub fn invoke_actions(&mut self, entity_idx: Idx) -> GameResult<Effects> {
+        self.sync_schedule();
         let mut effects = self.build_effects();
 
-        let entity = self.get_entity(entity_idx).unwrap();
-        let context = self.build_context(entity);
-        entity.object().plan_action(context, &mut effects);
+        let entity = self.get_entity(entity_idx).ok_or(GameError::MissingEntity { idx: entity_idx })?;
+        let context = self.build_context(entity)?;
+        if let Some(ai) = entity.ai() {
+            ai.plan_action(context, &mut effects);
+        }
 
         while let Some(action) = effects.actions.pop() {
-            //print(format!("action: {:?}", action));
-            self.execute_action(&mut effects, action);
+            let outcome = self.resolve(&mut effects, action, 0)?;
+            if effects.outcome.is_none() {
+                // a failed action costs nothing, so a creature repeating it would never let time pass;
+                // players are asked again instead
+                if !outcome.is_success() && !self.is_player(entity_idx) {
+                    trace!("{:?} failed with {:?}, waiting instead", action, outcome);
+                    self.execute_action(&mut effects, Action::Wait { idx: entity_idx })?;
+                }
+                effects.outcome = Some(outcome);
+            }
+        }
+
+        self.sync_schedule();
+        for idx in self.schedule.active() {
+            self.reschedule(idx);
+        }
+        Ok(effects)
+    }
+
+    // runs an action with everything it sets off, depth first:
+    // interrupts of every observer in idx order, each resolved completely, then the action,
+    // then updates, then reactions in idx order, each resolved completely;
+    // actions deeper than MAX_REACTION_DEPTH are not observed, so chains always end
+    fn resolve(&mut self, effects: &mut Effects, action: Action, depth: usize) -> GameResult<Outcome> {
+        if depth < MAX_REACTION_DEPTH {
+            for interrupt in self.observe(effects, |o, context, e| o.interrupt(context, &action, depth, e))? {
+                self.resolve(effects, interrupt, depth + 1)?;
+            }
+        }
 
-            self.levels.iter()
-                .flat_map(|(_, lvl)| lvl.get_entities())
-                .filter(|e| e.object().is_active())
-                .for_each(|entity| entity.object().update(self.build_context(entity), &mut effects));
+        trace!("action: {:?} at depth {}", action, depth);
+        let actor = action.actors()[0];
+        let able = self.get_entity(actor).map_or(false, |e| e.get_cooldown() != i64::max_value());
+        let outcome = if able { self.execute_action(effects, action)? } else { Outcome::Interrupted };
+
+        for idx in self.schedule.active() {
+            if let Some(entity) = self.get_entity(idx) {
+                if let Some(ai) = entity.ai() {
+                    ai.update(self.build_context(entity)?, effects);
+                }
+            }
         }
 
-        effects.messages
+        if depth < MAX_REACTION_DEPTH {
+            for reaction in self.observe(effects, |o, context, e| o.react(context, &action, &outcome, depth, e))? {
+                self.resolve(effects, reaction, depth + 1)?;
+            }
+        }
+        Ok(outcome)
     }
-    pub fn execute_action(&mut self, effects: &mut Effects, action: Action) {
+
+    // lets every living active entity look at an action, returns what they want to do about it
+    fn observe<F>(&self, effects: &mut Effects, hook: F) -> GameResult<Vec<Action>>
+        where F: Fn(&Object, Context, &mut Effects) {
+        for idx in self.schedule.active() {
+            match self.get_entity(idx) {
+                Some(entity) if entity.get_cooldown() != i64::max_value() => {
+                    if let Some(ai) = entity.ai() {
+                        hook(ai, self.build_context(entity)?, effects);
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(effects.reactions.drain(..).collect())
+    }
+
+    // only actions that succeed reach the ais, so failures cost no time
+    pub fn execute_action(&mut self, effects: &mut Effects, action: Action) -> GameResult<Outcome> {
         match action {
             Action::Attack { target, source } => {
-                let t = self.get_mut_entity(target).unwrap();
-                t.object_mut().execute_action(effects, &action);
-                let s = self.get_mut_entity(source).unwrap();
-                s.object_mut().execute_action(effects, &action);
+                let (level, position) = {
+                    let s = self.get_entity(source).ok_or(GameError::MissingEntity { idx: source })?;
+                    (s.level(), s.position())
+                };
+                let health = match self.get_entity(target) {
+                    Some(t) if t.level() == level && t.position().neumann_dist(position) == 1 =>
+                        t.components().health.map(|h| h.health),
+                    _ => None,
+                };
+                match health {
+                    None => return Ok(Outcome::InvalidTarget),
+                    Some(h) if h <= 0 => return Ok(Outcome::TargetDead),
+                    Some(_) => (),
+                }
+                effects.events.push(Event::Attacked { source, target });
+                if self.relation(target, source) == Relation::Neutral {
+                    if let (Some(a), Some(b)) = (self.faction(target).map(String::from), self.faction(source).map(String::from)) {
+                        self.factions.set(&a, &b, Relation::Hostile);
+                        effects.events.push(Event::Provoked { source, target });
+                    }
+                }
+                // entities without stats fight with the defaults
+                let combat = |idx: Idx| self.get_entity(idx).and_then(|e| e.components().combat).unwrap_or_default();
+                let (attacker, defender) = (combat(source), combat(target));
+                let damage = match roll_attack(&attacker, &defender, &mut effects.rand) {
+                    Roll::Miss => {
+                        effects.events.push(Event::Missed { source, target });
+                        0
+                    }
+                    Roll::Hit { damage, critical } => {
+                        effects.events.push(Event::Hit { source, target, damage, critical });
+                        damage
+                    }
+                };
+                let t = self.get_mut_entity(target).ok_or(GameError::MissingEntity { idx: target })?;
+                if let Some(health) = t.health_mut().filter(|_| damage > 0) {
+                    let amount = damage.min(health.health);
+                    health.health -= amount;
+                    effects.events.push(Event::Damaged { idx: target, amount, health: health.health });
+                    if health.health == 0 {
+                        effects.events.push(Event::Died { idx: target });
+                    }
+                }
+                let s = self.get_mut_entity(source).ok_or(GameError::MissingEntity { idx: source })?;
+                if let Some(ai) = s.ai_mut() {
+                    ai.execute_action(effects, &action);
+                }
             }
             Action::Walk { idx, position } => {
-                let mut lvl = self.get_mut_entity_level(idx).unwrap();
-                if !lvl.get_tile(position).map(Level::is_blocking).unwrap_or(true) {
-                    lvl.move_entity(idx, position);
+                let lvl = self.get_mut_entity_level(idx).ok_or(GameError::MissingEntity { idx })?;
+                let from = lvl.get_position(idx).ok_or(GameError::MissingEntity { idx })?;
+                if lvl.get_tile(position).is_none() {
+                    effects.events.push(Event::Blocked { idx, position, by: None });
+                    return Ok(Outcome::OutOfBounds);
+                }
+                if let Some(blocker) = lvl.get_blocker(position) {
+                    let by = blocker.idx();
+                    effects.events.push(Event::Blocked { idx, position, by: Some(by) });
+                    return Ok(Outcome::Blocked { by, name: blocker.name().to_string() });
+                }
+                let e = lvl.move_entity(idx, position)?;
+                effects.events.push(Event::Moved { idx, from, to: position });
+                if let Some(ai) = e.ai_mut() {
+                    ai.execute_action(effects, &action);
                 }
             }
             Action::Wait { idx } => {
-                let mut e = self.get_mut_entity(idx).unwrap();
-                e.object_mut().execute_action(effects, &action);
+                effects.events.push(Event::Waited { idx });
+                let e = self.get_mut_entity(idx).ok_or(GameError::MissingEntity { idx })?;
+                if let Some(ai) = e.ai_mut() {
+                    ai.execute_action(effects, &action);
+                }
             }
         };
+        Ok(Outcome::Succeeded)
     }
 }
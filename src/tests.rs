@@ -1,6 +1,42 @@
 
 use foundation::GameState;
 use utils::ipoint::IPoint;
+use state::save::SAVE_VERSION;
+use serde_json;
+use design::config::{GameConfig, PopulationConfig, RoomConfig};
+use design::blueprint::{Blueprint, Tile};
+use utils::irange::IRange;
+use logging;
+use logging::{BufferSink, LogLevel};
+use player::command::{Command, Direction};
+use player::player::PlayerData;
+use error::{GameError, take_last_error};
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::ptr;
+use std::collections::HashMap;
+use state::object::Pixel;
+use state::event::{Event, StopReason};
+use state::world::World;
+use state::level::{Level, SimulationPolicy};
+use objects::player::Player;
+use objects::wall::wall;
+use state::status::{StatusEffect, StatusEffects, StatusKind};
+use state::timer::WorldEvent;
+use replay::{replay, Input, Recording};
+use player::lifecycle::{DeathCause, GameStatus};
+use state::context::{Action, Outcome};
+use state::object::{Idx, Object};
+use state::context::{Context, Effects};
+use state::save::ObjectData;
+use objects::character::Character;
+use state::component::{Bundle, ComponentKind, Components};
+use design::templates::{AiProfile, CreatureTemplate, Templates};
+use state::faction::{Factions, Relation, Relationship};
+use state::stats::{roll_attack, Combat, Dice, Roll};
+use utils::xorshift::XorShift;
+use rand::{Rng, SeedableRng};
+use {press_key, create_world, create_world_with_config, destroy_world};
 
 #[test]
 fn it_works() {
@@ -9,12 +45,1113 @@ fn it_works() {
 
 #[test]
 fn do_move() {
-    let mut state = GameState::new(IPoint{x: 60, y: 60});
-    state.get_view();
-    state.process_key("a");
-    state.get_view();
-    state.process_key("w");
-    state.get_view();
-    state.process_key("s");
-    state.get_view();
+    let mut state = GameState::new(IPoint{x: 60, y: 60}).unwrap();
+    state.get_view().unwrap();
+    state.process_key("a").unwrap();
+    state.get_view().unwrap();
+    state.process_key("w").unwrap();
+    state.get_view().unwrap();
+    state.process_key("s").unwrap();
+    state.get_view().unwrap();
+}
+
+#[test]
+fn save_and_load() {
+    let mut state = GameState::new(IPoint{x: 60, y: 40}).unwrap();
+    state.process_key("a").unwrap();
+    state.process_key("s").unwrap();
+    let saved = state.save().unwrap();
+
+    let mut loaded = GameState::load(&saved).unwrap();
+    let resaved = loaded.save().unwrap();
+    let before: serde_json::Value = serde_json::from_str(&saved).unwrap();
+    let after: serde_json::Value = serde_json::from_str(&resaved).unwrap();
+    assert_eq!(before, after);
+
+    state.process_key("d").unwrap();
+    loaded.process_key("d").unwrap();
+    assert_eq!(state.get_view().unwrap(), loaded.get_view().unwrap());
+}
+
+#[test]
+fn load_rejects_newer_version() {
+    let state = GameState::new(IPoint{x: 20, y: 20}).unwrap();
+    let mut save: serde_json::Value = serde_json::from_str(&state.save().unwrap()).unwrap();
+    save["version"] = serde_json::Value::from(SAVE_VERSION + 1);
+    assert!(GameState::load(&save.to_string()).is_err());
+}
+
+#[test]
+fn same_config_same_world() {
+    let config: GameConfig = serde_json::from_str(
+        r#"{"size": {"x": 50, "y": 30}, "world_seed": [7, 7, 7, 7], "generator_seed": [9, 8, 7, 6]}"#
+    ).unwrap();
+    let mut first = GameState::from_config(config.clone()).unwrap();
+    let mut second = GameState::from_config(config.clone()).unwrap();
+    for key in ["a", "s", "d", "w", "d"].iter() {
+        first.process_key(key).unwrap();
+        second.process_key(key).unwrap();
+    }
+    let first: serde_json::Value = serde_json::from_str(&first.save().unwrap()).unwrap();
+    let second: serde_json::Value = serde_json::from_str(&second.save().unwrap()).unwrap();
+    assert_eq!(first, second);
+
+    let other = GameConfig { generator_seed: [1, 1, 1, 1], ..config.clone() };
+    let mut third = GameState::from_config(other).unwrap();
+    let mut fourth = GameState::from_config(config).unwrap();
+    assert_ne!(third.get_view().unwrap(), fourth.get_view().unwrap());
+}
+
+#[test]
+fn invalid_config() {
+    let config = GameConfig { world_seed: [0, 0, 0, 0], ..GameConfig::default() };
+    assert!(GameState::from_config(config).is_err());
+    let mut config = GameConfig::default();
+    config.rooms[0].max_size = IPoint{x: 100, y: 5};
+    assert!(GameState::from_config(config).is_err());
+
+    // too big to generate
+    let error = |config: GameConfig| config.validate().unwrap_err();
+    assert!(error(GameConfig::with_size(IPoint{x: 100000, y: 100000})).starts_with("size"));
+    let mut config = GameConfig::default();
+    config.rooms[0].count = 1000000000;
+    assert!(error(config.clone()).starts_with("rooms[0].count"));
+    config.rooms[0].count = 97;
+    assert!(error(config).starts_with("rooms[1].count"));
+}
+
+#[test]
+fn view_diff_patches_previous_view() {
+    let mut state = GameState::new(IPoint{x: 60, y: 40}).unwrap();
+    let first: serde_json::Value = serde_json::from_str(&state.get_view_diff(1, false).unwrap()).unwrap();
+    assert_eq!(first["full"], true);
+    assert_eq!(first["changes"].as_array().unwrap().len(), 60 * 40);
+
+    let unchanged: serde_json::Value = serde_json::from_str(&state.get_view_diff(1, false).unwrap()).unwrap();
+    assert_eq!(unchanged["full"], false);
+    assert!(unchanged["changes"].as_array().unwrap().is_empty());
+
+    let mut grid: serde_json::Value = serde_json::from_str(&state.get_view().unwrap()).unwrap();
+    state.process_key("s").unwrap();
+    state.process_key("d").unwrap();
+    let diff: serde_json::Value = serde_json::from_str(&state.get_view_diff(1, false).unwrap()).unwrap();
+    for change in diff["changes"].as_array().unwrap() {
+        let x = change["x"].as_u64().unwrap() as usize;
+        let y = change["y"].as_u64().unwrap() as usize;
+        grid[y][x] = change["pixel"].clone();
+    }
+    let expected: serde_json::Value = serde_json::from_str(&state.get_view().unwrap()).unwrap();
+    assert_eq!(grid, expected);
+
+    // other clients and forced resyncs get the whole grid
+    let other: serde_json::Value = serde_json::from_str(&state.get_view_diff(2, false).unwrap()).unwrap();
+    assert_eq!(other["full"], true);
+    let forced: serde_json::Value = serde_json::from_str(&state.get_view_diff(1, true).unwrap()).unwrap();
+    assert_eq!(forced["changes"].as_array().unwrap().len(), 60 * 40);
+}
+
+#[test]
+fn exports_report_errors() {
+    assert_eq!(press_key(ptr::null_mut(), ptr::null_mut()), -1);
+    let error = take_last_error().unwrap();
+    assert_eq!(error.error, GameError::NullPointer { argument: "pimpl".to_string() });
+
+    let game = create_world();
+    let mut invalid = vec![0xffu8 as c_char, 0];
+    assert_eq!(press_key(game, invalid.as_mut_ptr()), -2);
+    assert_eq!(take_last_error().unwrap().error, GameError::InvalidUtf8 { argument: "key".to_string() });
+
+    let config = CString::new("{\"size\": {\"x\": -1, \"y\": 5}}").unwrap();
+    assert!(create_world_with_config(config.as_ptr() as *mut c_char).is_null());
+    assert_eq!(take_last_error().unwrap().code, -5);
+    assert!(take_last_error().is_none());
+
+    let key = CString::new("a").unwrap();
+    assert_eq!(press_key(game, key.as_ptr() as *mut c_char), 0);
+    assert_eq!(destroy_world(game), 0);
+}
+
+#[test]
+fn missing_player_is_an_error() {
+    // monsters would go on acting without the player
+    let mut state = GameState::from_config(unpopulated(IPoint{x: 30, y: 30})).unwrap();
+    let idx = state.players[0].player;
+    state.game.get_mut_entity_level(idx).unwrap().remove_entity(idx);
+    assert_eq!(state.process_key("a"), Err(GameError::Stalled));
+    assert_eq!(state.get_view(), Err(GameError::MissingEntity { idx }));
+}
+
+#[test]
+fn submit_commands() {
+    let mut state = GameState::new(IPoint{x: 60, y: 40}).unwrap();
+    let idx = state.players[0].player;
+    let start = state.game.get_entity(idx).unwrap().position();
+
+    let wait: Command = serde_json::from_str(r#"{"type": "wait"}"#).unwrap();
+    assert_eq!(state.submit_command(wait), Ok(true));
+    assert_eq!(state.game.get_entity(idx).unwrap().position(), start);
+
+    // with the default config there is a free tile south of the start
+    let south: Command = serde_json::from_str(r#"{"type": "move", "dir": "south"}"#).unwrap();
+    assert_eq!(south, Command::Move { dir: Direction::South });
+    assert_eq!(state.submit_command(south), Ok(true));
+    assert_eq!(state.game.get_entity(idx).unwrap().position(), start + IPoint{x: 0, y: 1});
+
+    let attack: Command = serde_json::from_str(r#"{"type": "attack", "target": 12345}"#).unwrap();
+    assert_eq!(state.submit_command(attack), Ok(false));
+    assert!(serde_json::from_str::<Command>(r#"{"type": "fly"}"#).is_err());
+}
+
+#[test]
+fn trace_actions_per_module() {
+    let sink = BufferSink::new();
+    logging::set_sink(Box::new(sink.clone()));
+    logging::set_level(LogLevel::Off);
+    logging::set_module_level("autismarchia_back::state::world", LogLevel::Trace);
+
+    let mut state = GameState::new(IPoint{x: 60, y: 40}).unwrap();
+    state.process_key("s").unwrap();
+    let lines = sink.lines();
+    assert!(lines.iter().any(|l| l.starts_with("[TRACE autismarchia_back::state::world] action: Walk")));
+    assert!(lines.iter().all(|l| l.contains("autismarchia_back::state::world")));
+
+    logging::set_module_level("autismarchia_back::state", LogLevel::Warn);
+    logging::set_module_level("autismarchia_back::state::world", LogLevel::Info);
+    let count = sink.lines().len();
+    state.process_key("w").unwrap();
+    assert_eq!(sink.lines().len(), count);
+}
+
+#[test]
+fn inspect_hides_unseen_tiles() {
+    let mut state = GameState::new(IPoint{x: 60, y: 40}).unwrap();
+    let idx = state.players[0].player;
+    let start = state.game.get_entity(idx).unwrap().position();
+
+    let here: serde_json::Value = serde_json::from_str(&state.inspect(start).unwrap()).unwrap();
+    assert_eq!(here["visibility"], "visible");
+    assert_eq!(here["entities"][0]["idx"], idx);
+    assert_eq!(here["entities"][0]["stats"]["health"], 10);
+    assert_eq!(here["entities"][1]["name"], "Floor");
+    assert!(here["entities"][1]["stats"].is_null());
+
+    // tiles out of sight only show what the player remembers
+    let far = IPoint{x: 59, y: 39};
+    let unknown: serde_json::Value = serde_json::from_str(&state.inspect(far).unwrap()).unwrap();
+    assert_eq!(unknown["visibility"], "unknown");
+    let level = state.game.get_entity_level(idx).unwrap().idx();
+    state.players[0].views.entry(level).or_insert_with(HashMap::new).insert(far, Pixel::empty().gray());
+    let remembered: serde_json::Value = serde_json::from_str(&state.inspect(far).unwrap()).unwrap();
+    assert_eq!(remembered["visibility"], "remembered");
+    assert!(remembered.get("entities").is_none());
+
+    assert!(state.inspect(IPoint{x: 60, y: 0}).is_err());
+}
+
+#[test]
+fn events_are_recorded() {
+    let mut state = GameState::new(IPoint{x: 60, y: 40}).unwrap();
+    let idx = state.players[0].player;
+    let start = state.game.get_entity(idx).unwrap().position();
+    state.process_key("s").unwrap();
+    state.submit_command(Command::Wait).unwrap();
+    state.process_key("w").unwrap();
+    state.process_key("w").unwrap();
+
+    let events: serde_json::Value = serde_json::from_str(&state.get_events(-1).unwrap()).unwrap();
+    let events = events.as_array().unwrap();
+    assert_eq!(events.len(), 4);
+    assert_eq!(events[0]["event"], serde_json::to_value(
+        Event::Moved { idx, from: start, to: start + IPoint{x: 0, y: 1} }).unwrap());
+    assert_eq!(events[0]["turn"], 0);
+    assert_eq!(events[1]["event"]["type"], "Waited");
+    assert_eq!(events[2]["event"]["type"], "Moved");
+    // the player starts at the top edge of a room
+    assert_eq!(events[3]["event"]["type"], "Blocked");
+    assert_eq!(events[3]["event"]["position"], serde_json::to_value(start + IPoint{x: 0, y: -1}).unwrap());
+    assert_eq!(events[3]["turn"], 3);
+
+    let newer: serde_json::Value = serde_json::from_str(&state.get_events(2).unwrap()).unwrap();
+    assert_eq!(newer.as_array().unwrap().len(), 1);
+    assert_eq!(newer[0]["id"], 3);
+
+    // moves on another level go unseen, also where the same points are in sight
+    let mut world = World::new([1, 2, 3, 4]);
+    let (here, there, player, character) = (world.next_id(), world.next_id(), world.next_id(), world.next_id());
+    world.add_level(Level::new(here, IPoint{x: 3, y: 1})).unwrap().add_entity(Player::new(player), IPoint{x: 0, y: 0}).unwrap();
+    world.add_level(Level::new(there, IPoint{x: 3, y: 1})).unwrap().add_entity(Character::new(character), IPoint{x: 1, y: 0}).unwrap();
+    let effects = world.invoke_actions(character).unwrap();
+    assert!(effects.events.iter().any(|e| match e { Event::Moved { .. } => true, _ => false }));
+    let mut data = PlayerData::new(player);
+    data.record(&world, &effects).unwrap();
+    assert!(data.get_events(-1).is_empty());
+}
+
+#[test]
+fn hot_seat_players() {
+    let mut state = GameState::new(IPoint{x: 60, y: 40}).unwrap();
+    assert_eq!(state.add_player(), Ok(1));
+    let first = state.players[0].player;
+    let second = state.players[1].player;
+    assert_ne!(state.game.get_entity(first).unwrap().position(), state.game.get_entity(second).unwrap().position());
+
+    // players take turns in order of readiness, ties go to the older player
+    assert_eq!(state.turn_owner(), 0);
+    assert_eq!(state.submit_command(Command::Wait), Ok(true));
+    assert_eq!(state.turn_owner(), 1);
+    assert_eq!(state.submit_command(Command::Wait), Ok(true));
+    assert_eq!(state.turn_owner(), 0);
+    assert_eq!(state.players[0].turn, 1);
+    assert_eq!(state.players[1].turn, 1);
+
+    let saved = state.save().unwrap();
+    let loaded = GameState::load(&saved).unwrap();
+    assert_eq!(loaded.players.len(), 2);
+    assert_eq!(loaded.players[1].player, second);
+    assert_eq!(loaded.turn_owner(), 0);
+}
+
+#[test]
+fn load_single_player_save() {
+    let state = GameState::new(IPoint{x: 30, y: 30}).unwrap();
+    let mut save: serde_json::Value = serde_json::from_str(&state.save().unwrap()).unwrap();
+    let player = save["state"]["players"][0].take();
+    save["state"].as_object_mut().unwrap().remove("players");
+    save["state"]["player"] = player;
+    save["version"] = serde_json::Value::from(1);
+
+    let loaded = GameState::load(&save.to_string()).unwrap();
+    assert_eq!(loaded.players.len(), 1);
+    assert_eq!(loaded.players[0].player, state.players[0].player);
+}
+
+#[test]
+fn scheduler_follows_entities() {
+    let mut world = World::new([1, 2, 3, 4]);
+    let (first, second) = (world.next_id(), world.next_id());
+    let (upper, lower, stone) = (world.next_id(), world.next_id(), world.next_id());
+    {
+        let level = world.add_level(Level::new(upper, IPoint{x: 5, y: 5})).unwrap();
+        level.add_entity(wall(stone), IPoint{x: 0, y: 0}).unwrap();
+        level.add_entity(Player::new(second), IPoint{x: 1, y: 1}).unwrap();
+        level.add_entity(Player::new(first), IPoint{x: 2, y: 1}).unwrap();
+    }
+    world.add_level(Level::new(lower, IPoint{x: 5, y: 5})).unwrap();
+
+    // ties go to the lowest idx, a wait puts the first player behind the second
+    assert_eq!(world.lapse_time().unwrap().idx(), first);
+    world.invoke_actions(first).unwrap();
+    assert_eq!(world.lapse_time().unwrap().idx(), second);
+    assert_eq!(world.time(), 0);
+    world.invoke_actions(second).unwrap();
+    assert_eq!(world.lapse_time().unwrap().idx(), first);
+    assert_eq!(world.time(), 10);
+
+    // entities moved between levels keep their turn, removed ones lose it
+    world.transfer_entity(first, lower, IPoint{x: 3, y: 3}).unwrap();
+    assert_eq!(world.get_entity_level(first).unwrap().idx(), lower);
+    assert_eq!(world.lapse_time().unwrap().idx(), first);
+    world.get_mut_entity_level(second).unwrap().remove_entity(second).unwrap();
+    world.invoke_actions(first).unwrap();
+    assert_eq!(world.lapse_time().unwrap().idx(), first);
+    assert_eq!(world.time(), 20);
+    world.get_mut_entity_level(first).unwrap().remove_entity(first).unwrap();
+    assert_eq!(world.lapse_time().err(), Some(GameError::Stalled));
+
+    // entities added later are picked up, also after loading
+    let third = world.next_id();
+    world.get_mut_level(upper).unwrap().add_entity(Player::new(third), IPoint{x: 1, y: 1}).unwrap();
+    let mut loaded: World = serde_json::from_str(&serde_json::to_string(&world).unwrap()).unwrap();
+    assert_eq!(world.lapse_time().unwrap().idx(), third);
+    assert_eq!(loaded.lapse_time().unwrap().idx(), third);
+}
+
+#[test]
+fn status_effects() {
+    let mut effects = StatusEffects::default();
+    effects.add(StatusEffect::new(StatusKind::Poison, 20, 1));
+    effects.add(StatusEffect::new(StatusKind::Poison, 30, 2));
+    effects.add(StatusEffect::new(StatusKind::Slow, 30, 50));
+    effects.add(StatusEffect::new(StatusKind::Slow, 10, 100));
+    assert_eq!(effects.list(), &[
+        StatusEffect::new(StatusKind::Poison, 30, 3),
+        StatusEffect::new(StatusKind::Slow, 30, 100),
+    ]);
+    assert_eq!(effects.action_cost(10), 20);
+    // poison ticks at 10 and 20, then at 30 when everything expires
+    assert_eq!(effects.lapse_time(25), -6);
+    assert_eq!(effects.lapse_time(10), -3);
+    assert!(effects.list().is_empty());
+
+    let mut state = GameState::new(IPoint{x: 60, y: 40}).unwrap();
+    let idx = state.players[0].player;
+    assert_eq!(state.game.apply_status(idx, StatusEffect::new(StatusKind::Haste, 100, 100)), Ok(true));
+    state.submit_command(Command::Wait).unwrap();
+    assert_eq!(state.game.time(), 5);
+    state.game.apply_status(idx, StatusEffect::new(StatusKind::Stun, 20, 0)).unwrap();
+    state.submit_command(Command::Wait).unwrap();
+    assert_eq!(state.game.time(), 30);
+
+    state.game.apply_status(idx, StatusEffect::new(StatusKind::Poison, 20, 2)).unwrap();
+    for _ in 0..4 {
+        state.submit_command(Command::Wait).unwrap();
+    }
+    let status: serde_json::Value = serde_json::from_str(&state.get_player_status().unwrap()).unwrap();
+    assert_eq!(status["stats"]["health"], 6);
+    assert_eq!(status["effects"].as_array().unwrap().len(), 1);
+    assert_eq!(status["effects"][0]["kind"], "haste");
+    assert_eq!(state.game.apply_status(12345, StatusEffect::new(StatusKind::Stun, 20, 0)),
+               Err(GameError::MissingEntity { idx: 12345 }));
+}
+
+#[test]
+fn replay_reaches_recorded_state() {
+    let mut state = GameState::new(IPoint{x: 60, y: 40}).unwrap();
+    state.start_recording().unwrap();
+    for key in &["s", "d", "x", "d", "w"] {
+        state.process_key(key).unwrap();
+    }
+    state.add_player().unwrap();
+    state.submit_command(Command::Wait).unwrap();
+    state.submit_command(Command::Move { dir: Direction::West }).unwrap();
+
+    let json = serde_json::to_string(state.recording().unwrap()).unwrap();
+    let recording: Recording = serde_json::from_str(&json).unwrap();
+    assert_eq!(recording.steps.len(), 8);
+    assert_eq!(recording.steps[2].input, Input::Key { key: "x".to_string() });
+    let replayed = replay(&recording).unwrap();
+    assert_eq!(replayed.game.state_hash(), state.game.state_hash());
+    assert_eq!(replayed.recording(), state.recording());
+
+    // a different input shows up at its own step
+    let mut changed = recording.clone();
+    changed.steps[3].input = Input::Key { key: "a".to_string() };
+    match replay(&changed) {
+        Err(GameError::ReplayDiverged { step, .. }) => assert_eq!(step, 4),
+        _ => panic!("replay did not diverge"),
+    }
+
+    // recordings started mid game can not be rebuilt from the config
+    assert!(state.stop_recording().is_some());
+    state.start_recording().unwrap();
+    match replay(state.recording().unwrap()) {
+        Err(GameError::ReplayDiverged { step, .. }) => assert_eq!(step, 0),
+        _ => panic!("replay did not diverge"),
+    }
+}
+
+#[test]
+fn undo_and_redo() {
+    let mut state = GameState::new(IPoint{x: 60, y: 40}).unwrap();
+    state.submit_command(Command::Wait).unwrap();
+    assert_eq!(state.undo(1), Ok(0));
+
+    let config = GameConfig { undo_limit: 2, ..GameConfig::default() };
+    let mut state = GameState::from_config(config).unwrap();
+    state.start_recording().unwrap();
+    let idx = state.players[0].player;
+    let position = |state: &GameState| state.game.get_entity(idx).unwrap().position();
+    let start = position(&state);
+    let south = Command::Move { dir: Direction::South };
+    state.submit_command(south).unwrap();
+    state.submit_command(south).unwrap();
+    state.submit_command(south).unwrap();
+    assert_eq!(position(&state), start + IPoint{x: 0, y: 3});
+
+    // only the last two turns are kept
+    assert_eq!(state.undo(5), Ok(2));
+    assert_eq!(position(&state), start + IPoint{x: 0, y: 1});
+    assert_eq!(state.game.time(), 10);
+    assert_eq!(state.redo(1), Ok(1));
+    assert_eq!(position(&state), start + IPoint{x: 0, y: 2});
+
+    // a new turn drops what was undone
+    state.submit_command(Command::Wait).unwrap();
+    assert_eq!(state.redo(1), Ok(0));
+    assert_eq!(state.undo(1), Ok(1));
+    assert_eq!(position(&state), start + IPoint{x: 0, y: 2});
+
+    let replayed = replay(state.recording().unwrap()).unwrap();
+    assert_eq!(replayed.game.state_hash(), state.game.state_hash());
+}
+
+#[test]
+fn death_ends_the_game() {
+    let mut state = GameState::new(IPoint{x: 60, y: 40}).unwrap();
+    let idx = state.players[0].player;
+    state.game.apply_status(idx, StatusEffect::new(StatusKind::Poison, 10, 20)).unwrap();
+    state.submit_command(Command::Wait).unwrap();
+    assert_eq!(state.status, GameStatus::Dead {
+        cause: DeathCause::Poisoned,
+        message: "You succumbed to poison on turn 1.".to_string(),
+    });
+    assert_eq!(state.players[0].get_messages().last().unwrap(), "You succumbed to poison on turn 1.");
+    assert_eq!(state.process_key("s"), Err(GameError::GameOver));
+    assert_eq!(state.submit_command(Command::Wait), Err(GameError::GameOver));
+    let status: serde_json::Value = serde_json::from_str(&state.get_game_status().unwrap()).unwrap();
+    assert_eq!(status["state"], "dead");
+    assert_eq!(status["cause"]["type"], "poisoned");
+
+    state.restart().unwrap();
+    assert_eq!(state.status, GameStatus::Playing);
+    assert_eq!(state.submit_command(Command::Wait), Ok(true));
+
+    // health lost without an attack or poison is not blamed on either
+    let idx = state.players[0].player;
+    state.game.get_mut_entity(idx).unwrap().health_mut().unwrap().health = 0;
+    assert_eq!(state.submit_command(Command::Wait), Err(GameError::GameOver));
+    assert_eq!(state.players[0].death, Some(DeathCause::Unknown));
+    assert_eq!(state.players[0].get_messages().last().unwrap(), "You died on turn 1.");
+}
+
+#[test]
+fn hot_seat_death() {
+    let mut state = GameState::new(IPoint{x: 60, y: 40}).unwrap();
+    state.add_player().unwrap();
+    let (first, second) = (state.players[0].player, state.players[1].player);
+    let distance = state.game.get_entity(first).unwrap().position()
+        .neumann_dist(state.game.get_entity(second).unwrap().position());
+    assert_eq!(distance, 1);
+
+    // the second player beats the first one to death, the game goes on with the survivor
+    while state.players[0].death.is_none() {
+        assert!(state.game.time() < 1000);
+        assert_eq!(state.turn_owner(), 0);
+        state.submit_command(Command::Wait).unwrap();
+        assert_eq!(state.submit_command(Command::Attack { target: first }), Ok(true));
+    }
+    assert_eq!(state.players[0].death, Some(DeathCause::Killed { by: second, name: "player".to_string() }));
+    assert_eq!(state.status, GameStatus::Playing);
+    assert_eq!(state.turn_owner(), 1);
+    assert_eq!(state.submit_command(Command::Wait), Ok(true));
+    assert_eq!(state.turn_owner(), 1);
+}
+
+#[test]
+fn death_by_interrupt() {
+    let mut state = GameState::from_config(unpopulated(IPoint{x: 60, y: 40})).unwrap();
+    let player = state.players[0].player;
+    let position = state.game.get_entity(player).unwrap().position();
+    let free: Vec<IPoint> = {
+        let level = state.game.get_entity_level(player).unwrap();
+        position.neumann_surrounding().into_iter().filter(|p| !level.is_blocking(*p)).collect()
+    };
+    // a monster that always hits, next to the player who has 1 health left
+    let monster = state.game.next_id();
+    let template = CreatureTemplate { name: "rat".to_string(), ..CreatureTemplate::default() };
+    state.game.get_mut_entity_level(player).unwrap().add_entity(template.build(monster), free[0]).unwrap();
+    state.game.get_mut_entity(player).unwrap().health_mut().unwrap().health = 1;
+    let dir = [Direction::North, Direction::South, Direction::East, Direction::West].iter()
+        .cloned()
+        .find(|d| free[1..].contains(&(position + d.offset())))
+        .unwrap();
+
+    // stepping away is cut short by the attack of opportunity
+    assert_eq!(state.submit_command(Command::Move { dir }), Ok(false));
+    assert_eq!(state.players[0].death, Some(DeathCause::Killed { by: monster, name: "rat".to_string() }));
+    let status: serde_json::Value = serde_json::from_str(&state.get_game_status().unwrap()).unwrap();
+    assert_eq!(status["state"], "dead");
+    assert_eq!(state.players[0].get_messages().last().unwrap(), "You were killed by the rat on turn 0.");
+    assert_eq!(state.submit_command(Command::Wait), Err(GameError::GameOver));
+}
+
+#[test]
+fn failed_actions_cost_nothing() {
+    let mut state = GameState::new(IPoint{x: 60, y: 40}).unwrap();
+    // the player starts at the top edge of a room
+    assert_eq!(state.submit_command(Command::Move { dir: Direction::North }), Ok(false));
+    assert_eq!(state.players[0].get_messages().last().unwrap(), "You hit the wall.");
+    assert_eq!(state.submit_command(Command::Attack { target: 12345 }), Ok(false));
+    assert_eq!(state.players[0].get_messages().last().unwrap(), "There is nothing to attack there.");
+    assert_eq!(state.get_messages(1), Ok("[\"There is nothing to attack there.\"]".to_string()));
+    assert_eq!(state.get_messages(-1), Ok("[]".to_string()));
+    assert_eq!((state.game.time(), state.players[0].turn), (0, 0));
+    assert_eq!(state.submit_command(Command::Move { dir: Direction::South }), Ok(true));
+    assert_eq!((state.game.time(), state.players[0].turn), (10, 1));
+
+    let mut world = World::new([1, 2, 3, 4]);
+    let (level, first, second) = (world.next_id(), world.next_id(), world.next_id());
+    {
+        let level = world.add_level(Level::new(level, IPoint{x: 3, y: 1})).unwrap();
+        level.add_entity(Player::new(first), IPoint{x: 0, y: 0}).unwrap();
+        level.add_entity(Player::new(second), IPoint{x: 1, y: 0}).unwrap();
+    }
+    let mut effects = world.build_effects();
+    let walk = Action::Walk { idx: first, position: IPoint{x: -1, y: 0} };
+    assert_eq!(world.execute_action(&mut effects, walk), Ok(Outcome::OutOfBounds));
+    let walk = Action::Walk { idx: first, position: IPoint{x: 1, y: 0} };
+    assert_eq!(world.execute_action(&mut effects, walk),
+               Ok(Outcome::Blocked { by: second, name: "Character".to_string() }));
+    let attack = Action::Attack { source: first, target: second };
+    for _ in 0..10 {
+        assert_eq!(world.execute_action(&mut effects, attack), Ok(Outcome::Succeeded));
+    }
+    assert_eq!(world.execute_action(&mut effects, attack), Ok(Outcome::TargetDead));
+    let attack = Action::Attack { source: second, target: second };
+    assert_eq!(world.execute_action(&mut effects, attack), Ok(Outcome::InvalidTarget));
+}
+
+// player next to a monster that hunts them, the player attacks it and then walks away
+fn skirmish() -> Vec<Event> {
+    let mut world = World::new([1, 2, 3, 4]);
+    let (level, player, character) = (world.next_id(), world.next_id(), world.next_id());
+    let templates = Templates::default();
+    {
+        let level = world.add_level(Level::new(level, IPoint{x: 5, y: 1})).unwrap();
+        level.add_entity(templates.build("player", player).unwrap(), IPoint{x: 1, y: 0}).unwrap();
+        level.add_entity(templates.build("monster", character).unwrap(), IPoint{x: 2, y: 0}).unwrap();
+    }
+    let mut events = Vec::new();
+    for action in &[Action::Attack { source: player, target: character },
+                    Action::Walk { idx: player, position: IPoint{x: 0, y: 0} }] {
+        world.get_mut_entity(player).unwrap().as_player().unwrap().set_action(*action);
+        events.extend(world.invoke_actions(player).unwrap().events);
+    }
+    events.into_iter().filter(|e| match e { Event::SpottedEnemy { .. } => false, _ => true }).collect()
+}
+
+#[test]
+fn reactions_resolve_in_order() {
+    let events = skirmish();
+    let (player, character) = (1, 2);
+    assert_eq!(events, vec![
+        Event::Attacked { source: player, target: character },
+        Event::Hit { source: player, target: character, damage: 3, critical: false },
+        Event::Damaged { idx: character, amount: 3, health: 7 },
+        // counterattack
+        Event::Attacked { source: character, target: player },
+        Event::Hit { source: character, target: player, damage: 2, critical: false },
+        Event::Damaged { idx: player, amount: 2, health: 8 },
+        // attack of opportunity, before the player gets away
+        Event::Attacked { source: character, target: player },
+        Event::Hit { source: character, target: player, damage: 3, critical: false },
+        Event::Damaged { idx: player, amount: 3, health: 5 },
+        Event::Moved { idx: player, from: IPoint{x: 1, y: 0}, to: IPoint{x: 0, y: 0} },
+    ]);
+    assert_eq!(skirmish(), events);
+}
+
+// waits whenever anything waits, to run into the depth limit
+struct Echo(Idx);
+
+impl Object for Echo {
+    fn get_idx(&self) -> Idx { self.0 }
+    fn save(&self) -> ObjectData { ObjectData::Player(Player::new(self.0)) }
+    fn is_active(&self) -> bool { true }
+    fn get_cooldown(&self) -> i64 { 0 }
+    fn react(&self, _context: Context, action: &Action, _outcome: &Outcome, _depth: usize, effects: &mut Effects) {
+        if let Action::Wait { .. } = action {
+            effects.reactions.push(Action::Wait { idx: self.0 });
+        }
+    }
+}
+
+#[test]
+fn reaction_depth_limit() {
+    let mut world = World::new([1, 2, 3, 4]);
+    let (level, player, first, second) = (world.next_id(), world.next_id(), world.next_id(), world.next_id());
+    {
+        let level = world.add_level(Level::new(level, IPoint{x: 3, y: 1})).unwrap();
+        level.add_entity(Player::new(player), IPoint{x: 0, y: 0}).unwrap();
+        for (idx, x) in vec![(first, 1), (second, 2)] {
+            let echo = Bundle { idx, components: Components::named("Echo"), ai: Some(Box::new(Echo(idx))) };
+            level.add_entity(echo, IPoint{x, y: 0}).unwrap();
+        }
+    }
+    let waited: Vec<Idx> = world.invoke_actions(player).unwrap().events.into_iter()
+        .filter_map(|e| match e { Event::Waited { idx } => Some(idx), _ => None })
+        .collect();
+    // 1 + 2 + 4 + 8 waits, depth first in idx order
+    assert_eq!(waited.len(), 15);
+    assert_eq!(&waited[..5], &[player, first, first, first, second]);
+}
+
+fn last_stop(state: &GameState) -> Option<StopReason> {
+    state.players[0].events.iter().rev().filter_map(|e| match e.event {
+        Event::Stopped { reason, .. } => Some(reason),
+        _ => None,
+    }).next()
+}
+
+#[test]
+fn multi_turn_commands() {
+    let mut state = GameState::new(IPoint{x: 60, y: 40}).unwrap();
+    let idx = state.players[0].player;
+    // down the room into its bottom wall, then along it until a doorway opens to the side
+    assert_eq!(state.submit_command(Command::Run { dir: Direction::South }), Ok(true));
+    assert_eq!(last_stop(&state), Some(StopReason::Failed));
+    assert_eq!(state.players[0].get_messages().last().unwrap(), "You hit the wall.");
+    assert_eq!((state.game.get_entity(idx).unwrap().position(), state.game.time()), (IPoint{x: 12, y: 11}, 100));
+    assert_eq!(state.submit_command(Command::Run { dir: Direction::East }), Ok(true));
+    assert_eq!(last_stop(&state), Some(StopReason::Junction));
+    assert_eq!(state.game.get_entity(idx).unwrap().position(), IPoint{x: 16, y: 11});
+
+    assert_eq!(state.submit_command(Command::Rest), Ok(false));
+    assert_eq!(last_stop(&state), Some(StopReason::Healed));
+    assert_eq!(state.players[0].get_messages().last().unwrap(), "You feel rested.");
+    state.game.apply_status(idx, StatusEffect::new(StatusKind::Poison, 20, 2)).unwrap();
+    state.submit_command(Command::Wait).unwrap();
+    assert_eq!(state.submit_command(Command::Rest), Ok(true));
+    assert_eq!(last_stop(&state), Some(StopReason::Damaged { amount: 2 }));
+    state.submit_command(Command::Wait).unwrap();
+    state.game.apply_status(idx, StatusEffect::new(StatusKind::Regeneration, 100, 1)).unwrap();
+    let time = state.game.time();
+    assert_eq!(state.submit_command(Command::Rest), Ok(true));
+    assert_eq!(last_stop(&state), Some(StopReason::Healed));
+    assert_eq!(state.game.time(), time + 40);
+
+    // repeats the wait rest ended with
+    let time = state.game.time();
+    assert_eq!(state.submit_command(Command::Repeat { count: 3 }), Ok(true));
+    assert_eq!(last_stop(&state), Some(StopReason::Completed));
+    assert_eq!(state.game.time(), time + 30);
+    let mut fresh = GameState::new(IPoint{x: 60, y: 40}).unwrap();
+    assert_eq!(fresh.submit_command(Command::Repeat { count: 3 }), Ok(false));
+    assert_eq!(fresh.players[0].get_messages().last().unwrap(), "There is nothing to repeat.");
+}
+
+// a poisoned character on a level of its own, health after the player waited there and came back
+fn away_from(policy: SimulationPolicy) -> (i32, i32, Vec<Idx>) {
+    let mut world = World::new([1, 2, 3, 4]);
+    let (here, there, player, character) = (world.next_id(), world.next_id(), world.next_id(), world.next_id());
+    world.add_level(Level::new(here, IPoint{x: 3, y: 1})).unwrap()
+        .add_entity(Player::new(player), IPoint{x: 0, y: 0}).unwrap();
+    {
+        let level = world.add_level(Level::new(there, IPoint{x: 3, y: 1})).unwrap();
+        level.set_policy(policy);
+        level.add_entity(Character::new(character), IPoint{x: 2, y: 0}).unwrap();
+    }
+    world.apply_status(character, StatusEffect::new(StatusKind::Poison, 100, 1)).unwrap();
+    let health = |world: &World| world.get_entity(character).unwrap().components().health.unwrap().health;
+    let act = |world: &mut World| {
+        let idx = world.lapse_time().unwrap().idx();
+        if idx == player {
+            world.get_mut_entity(player).unwrap().as_player().unwrap().set_action(Action::Wait { idx });
+        }
+        world.invoke_actions(idx).unwrap();
+    };
+    while world.time() < 50 {
+        act(&mut world);
+    }
+    let away = health(&world);
+    let active = world.active_entities();
+    world.transfer_entity(player, there, IPoint{x: 0, y: 0}).unwrap();
+    act(&mut world);
+    (away, health(&world), active)
+}
+
+#[test]
+fn level_simulation_policies() {
+    assert_eq!(away_from(SimulationPolicy::Full), (5, 5, vec![2, 3]));
+    // paused levels keep their entities, they just do not act
+    assert_eq!(away_from(SimulationPolicy::Frozen), (10, 10, vec![2, 3]));
+    assert_eq!(away_from(SimulationPolicy::CatchUp), (10, 5, vec![2, 3]));
+}
+
+#[test]
+fn timers_fire_and_survive_saves() {
+    let config = GameConfig { wandering_monsters: 30, ..unpopulated(GameConfig::default().size) };
+    let mut state = GameState::from_config(config).unwrap();
+    let idx = state.players[0].player;
+    let level = state.game.get_entity(idx).unwrap().level();
+    assert_eq!(state.game.schedule_event(0, Some(0), WorldEvent::Announce { message: "Never.".to_string() }),
+               Err(GameError::InvalidTimer { message: "every must be positive, got 0".to_string() }));
+    state.game.schedule_event(15, Some(20), WorldEvent::Announce { message: "The bell tolls.".to_string() }).unwrap();
+    state.game.apply_status(idx, StatusEffect::new(StatusKind::Haste, 1000, 100)).unwrap();
+    state.game.schedule_event(12, None, WorldEvent::ExpireStatus { idx, kind: StatusKind::Haste }).unwrap();
+
+    while state.game.time() < 30 {
+        state.submit_command(Command::Wait).unwrap();
+    }
+    assert_eq!(state.players[0].get_messages().last().unwrap(), "The bell tolls.");
+    let status: serde_json::Value = serde_json::from_str(&state.get_player_status().unwrap()).unwrap();
+    // hasted waits until the haste expired at 12
+    assert_eq!(status["time"], 35);
+    assert_eq!(status["effects"].as_array().unwrap().len(), 0);
+    // the player and the wandering monster
+    assert_eq!(state.game.active_entities().len(), 2);
+    let at: Vec<i64> = state.game.timers().iter().map(|t| t.at).collect();
+    assert_eq!(at, vec![55, 60]);
+    assert_eq!(state.game.timers()[1].event, WorldEvent::SpawnMonster { level, template: "monster".to_string() });
+
+    let loaded = GameState::load(&state.save().unwrap()).unwrap();
+    assert_eq!(loaded.game.timers(), state.game.timers());
+    assert_eq!(loaded.game.state_hash(), state.game.state_hash());
+    assert!(state.game.cancel_event(state.game.timers()[0].id));
+    assert_eq!(state.game.timers().len(), 1);
+}
+
+// turns a current save back into version 2, where every entity was one object holding its health
+fn downgrade_to_v2(save: &mut serde_json::Value) {
+    for (_, level) in save["state"]["game"]["levels"].as_object_mut().unwrap().iter_mut() {
+        for tile in level["tiles"].as_array_mut().unwrap() {
+            for entity in tile[1].as_array_mut().unwrap() {
+                let idx = entity["idx"].clone();
+                let components = entity["components"].take();
+                let mut object = match entity.get("ai") {
+                    Some(ai) if !ai.is_null() => ai.clone(),
+                    _ => {
+                        let mut data = serde_json::Map::new();
+                        data.insert("idx".to_string(), idx);
+                        let mut object = serde_json::Map::new();
+                        object.insert(components["name"].as_str().unwrap().to_string(), serde_json::Value::Object(data));
+                        serde_json::Value::Object(object)
+                    }
+                };
+                if let Some(health) = components.get("health") {
+                    for (_, data) in object.as_object_mut().unwrap().iter_mut() {
+                        data["health"] = health["health"].clone();
+                        data["max_health"] = health["max_health"].clone();
+                    }
+                }
+                let fields = entity.as_object_mut().unwrap();
+                fields.remove("idx");
+                fields.remove("components");
+                fields.remove("ai");
+                fields.insert("object".to_string(), object);
+            }
+        }
+    }
+    save["version"] = serde_json::Value::from(2);
+}
+
+#[test]
+fn load_object_save() {
+    let mut state = GameState::new(IPoint{x: 30, y: 30}).unwrap();
+    state.process_key("d").unwrap();
+    let idx = state.players[0].player;
+    state.game.get_mut_entity(idx).unwrap().health_mut().unwrap().health = 4;
+    let mut save: serde_json::Value = serde_json::from_str(&state.save().unwrap()).unwrap();
+    downgrade_to_v2(&mut save);
+
+    let loaded = GameState::load(&save.to_string()).unwrap();
+    assert_eq!(loaded.game.get_entity(idx).unwrap().components().health.unwrap().health, 4);
+    assert_eq!(loaded.game.state_hash().unwrap(), state.game.state_hash().unwrap());
+    let level = loaded.game.get_entity_level(idx).unwrap();
+    assert!(level.query(ComponentKind::Ai).any(|e| e.idx() == idx));
+    assert!(level.query(ComponentKind::Blocker).count() > 0);
+}
+
+#[test]
+fn creature_templates() {
+    let error = |json: &str| match Templates::from_json(json) {
+        Err(GameError::InvalidTemplate { message }) => message,
+        other => panic!("expected an invalid template, got {:?}", other.map(|t| t.names().len())),
+    };
+    assert!(error(r#"[{"name": "rat", "walk_sped": 5}]"#).contains("walk_sped"));
+    assert!(error(r#"[{"name": "rat", "attack_speed": 0}]"#).starts_with("creatures[0].attack_speed"));
+    assert!(error(r#"[{"name": "rat"}, {"name": "rat"}]"#).starts_with("creatures[1].name"));
+
+    let templates = Templates::from_json(r#"[
+        {"name": "rat", "health": 3, "damage": 2, "faction": "vermin", "ai": "guard"}
+    ]"#).unwrap();
+    assert_eq!(templates.names(), vec!["rat"]);
+    let mut level = Level::new(0, IPoint{x: 5, y: 5});
+    let rat = templates.spawn("rat", &mut level, 7, IPoint{x: 2, y: 2}).unwrap();
+    assert_eq!(rat.name(), "rat");
+    assert_eq!(rat.components().health.unwrap().max_health, 3);
+    assert_eq!(rat.components().faction, Some("vermin".to_string()));
+    assert!(templates.spawn("dragon", &mut level, 8, IPoint{x: 1, y: 1}).is_err());
+
+    // the config brings its own creatures, wandering monsters and players are made from them
+    let mut config = unpopulated(GameConfig::default().size);
+    let mut guard = config.creatures[1].clone();
+    guard.name = "guard".to_string();
+    guard.ai = AiProfile::Guard;
+    guard.damage = Dice::flat(3);
+    config.creatures.push(guard);
+    config.wandering_template = "guard".to_string();
+    config.wandering_monsters = 10;
+    config.player_template = "guard".to_string();
+    assert!(GameState::from_config(config.clone()).is_err());
+    config.player_template = "player".to_string();
+
+    let mut state = GameState::from_config(config).unwrap();
+    let player = state.players[0].player;
+    while state.game.time() < 10 {
+        state.submit_command(Command::Wait).unwrap();
+    }
+    let spawned = *state.game.active_entities().iter().find(|idx| **idx != player).unwrap();
+    let loaded = GameState::load(&state.save().unwrap()).unwrap();
+    let guard = loaded.game.get_entity(spawned).unwrap();
+    assert_eq!(guard.name(), "guard");
+    assert_eq!(guard.components().combat.unwrap().damage, Dice::flat(3));
+    assert_eq!(loaded.game.templates().names(), vec!["guard", "monster", "player"]);
+
+    // players see as far as their template says
+    let sight = |range: f32| {
+        let mut world = World::new([1, 2, 3, 4]);
+        let (level, player) = (world.next_id(), world.next_id());
+        let template = CreatureTemplate { name: "scout".to_string(), sight_range: range, ai: AiProfile::Player, ..CreatureTemplate::default() };
+        let level = world.add_level(Level::new(level, IPoint{x: 30, y: 1})).unwrap();
+        level.add_entity(template.build(player), IPoint{x: 0, y: 0}).unwrap();
+        PlayerData::new(player).build_view(&world).unwrap().tiles.keys().map(|p| p.x).max()
+    };
+    assert_eq!(sight(3.0), Some(3));
+    assert_eq!(sight(10.0), Some(10));
+}
+
+// a level without monsters until something spawns them
+fn unpopulated(size: IPoint) -> GameConfig {
+    let mut config = GameConfig::with_size(size);
+    config.population.budget = 0;
+    config
+}
+
+// the monsters of the player's level with where they stand and what they cost
+fn population(state: &GameState) -> Vec<(Idx, IPoint, u32)> {
+    let player = state.players[0].player;
+    let level = state.game.get_entity_level(player).unwrap();
+    level.query(ComponentKind::Ai)
+        .filter(|e| e.idx() != player)
+        .map(|e| (e.idx(), e.position(), state.game.templates().get(e.name()).unwrap().difficulty))
+        .collect()
+}
+
+#[test]
+fn populated_levels() {
+    let mut config = GameConfig::default();
+    assert!(population(&GameState::from_config(config.clone()).unwrap()).len() > 0);
+    config.population.budget = 0;
+    assert!(population(&GameState::from_config(config.clone()).unwrap()).is_empty());
+
+    let mut brute = config.creatures[1].clone();
+    brute.name = "brute".to_string();
+    brute.difficulty = 3;
+    config.creatures.push(brute);
+    config.population.templates = vec!["monster".to_string(), "brute".to_string()];
+    config.population.density = 0.2;
+    config.population.min_player_distance = 10;
+    config.population.budget = 7;
+
+    let state = GameState::from_config(config.clone()).unwrap();
+    let start = state.game.get_entity(state.players[0].player).unwrap().position();
+    let monsters = population(&state);
+    assert!(monsters.len() > 1);
+    let cost: u32 = monsters.iter().map(|m| m.2).sum();
+    assert!(cost <= 7 && cost >= 5);
+    assert!(monsters.iter().all(|m| m.1.neumann_dist(start) >= 10));
+    assert_eq!(population(&GameState::from_config(config.clone()).unwrap()), monsters);
+
+    config.population.templates.push("player".to_string());
+    assert!(GameState::from_config(config).is_err());
+
+    // a room too small for a whole monster at this density still gets one
+    let mut config = unpopulated(IPoint{x: 20, y: 20});
+    config.rooms = vec![RoomConfig { count: 1, min_size: IPoint{x: 3, y: 3}, max_size: IPoint{x: 4, y: 4}, min_distance: 5 }];
+    config.population.budget = 1;
+    config.population.min_player_distance = 0;
+    assert_eq!(population(&GameState::from_config(config).unwrap()).len(), 1);
+
+    // distances are walked around walls, here between two rooms joined at the bottom
+    let mut world = World::new([1, 2, 3, 4]);
+    let mut blueprint = Blueprint::new(IPoint{x: 7, y: 3}, [1, 2, 3, 4]);
+    blueprint.rooms = vec![
+        IRange { start: IPoint{x: 0, y: 0}, end: IPoint{x: 3, y: 3} },
+        IRange { start: IPoint{x: 4, y: 0}, end: IPoint{x: 7, y: 3} },
+    ];
+    blueprint.build_rooms();
+    blueprint.tiles.insert(IPoint{x: 3, y: 2}, Tile::Room);
+    let level = blueprint.level_from_blueprint(&mut world).unwrap().idx();
+    let rules = PopulationConfig { density: 1.0, min_player_distance: 3, budget: 100, templates: vec!["monster".to_string()] };
+    let monsters = blueprint.populate(&mut world, level, &rules, IPoint{x: 2, y: 0}).unwrap();
+    let positions: Vec<IPoint> = monsters.iter().map(|idx| world.get_entity(*idx).unwrap().position()).collect();
+    // two tiles from the start as the crow flies, six steps walking
+    assert!(positions.contains(&IPoint{x: 4, y: 0}));
+    assert!(!positions.contains(&IPoint{x: 2, y: 2}));
+}
+
+// a guard of a faction of its own, it does not wander off
+fn member(idx: Idx, faction: &str) -> Bundle {
+    let template = CreatureTemplate {
+        name: faction.to_string(),
+        faction: faction.to_string(),
+        ai: AiProfile::Guard,
+        ..CreatureTemplate::default()
+    };
+    template.build(idx)
+}
+
+#[test]
+fn faction_relations() {
+    let factions = Factions::default();
+    assert_eq!(factions.relation("players", "monsters"), Relation::Hostile);
+    assert_eq!(factions.relation("monsters", "monsters"), Relation::Allied);
+    assert_eq!(factions.relation("monsters", "traders"), Relation::Neutral);
+    let twice = vec![
+        Relationship { between: ("a".to_string(), "b".to_string()), relation: Relation::Allied },
+        Relationship { between: ("b".to_string(), "a".to_string()), relation: Relation::Hostile },
+    ];
+    assert!(Factions::new(&twice).unwrap_err().starts_with("relations[1].between"));
+
+    // a wolf between two sheep and a trader next to the player
+    let mut world = World::new([1, 2, 3, 4]);
+    let (level, wolf, sheep, other, player, trader) =
+        (world.next_id(), world.next_id(), world.next_id(), world.next_id(), world.next_id(), world.next_id());
+    {
+        let level = world.add_level(Level::new(level, IPoint{x: 6, y: 1})).unwrap();
+        level.add_entity(member(sheep, "sheep"), IPoint{x: 0, y: 0}).unwrap();
+        level.add_entity(member(wolf, "wolves"), IPoint{x: 1, y: 0}).unwrap();
+        level.add_entity(member(other, "sheep"), IPoint{x: 2, y: 0}).unwrap();
+        level.add_entity(Player::new(player), IPoint{x: 4, y: 0}).unwrap();
+        level.add_entity(member(trader, "traders"), IPoint{x: 5, y: 0}).unwrap();
+    }
+    let attacks = |events: Vec<Event>| -> Vec<(Idx, Idx)> {
+        events.into_iter().filter_map(|e| match e {
+            Event::Attacked { source, target } => Some((source, target)),
+            _ => None,
+        }).collect()
+    };
+    // neutral sheep are left alone until wolves hunt them, then the one with the lower idx
+    // of the two as close, the wolf spots it only after its turn
+    assert_eq!(attacks(world.invoke_actions(wolf).unwrap().events), vec![]);
+    world.set_relation("wolves", "sheep", Relation::Hostile);
+    assert_eq!(attacks(world.invoke_actions(wolf).unwrap().events), vec![]);
+    assert_eq!(attacks(world.invoke_actions(wolf).unwrap().events), vec![(wolf, sheep), (sheep, wolf)]);
+
+    // the attacked trader counterattacks and stays hostile, also after loading
+    world.get_mut_entity(player).unwrap().as_player().unwrap().set_action(Action::Attack { source: player, target: trader });
+    let events = world.invoke_actions(player).unwrap().events;
+    assert!(events.contains(&Event::Provoked { source: player, target: trader }));
+    assert_eq!(attacks(events), vec![(player, trader), (trader, player)]);
+    let mut world: World = serde_json::from_str(&serde_json::to_string(&world).unwrap()).unwrap();
+    assert_eq!(world.relation(trader, player), Relation::Hostile);
+    assert_eq!(world.relation(wolf, other), Relation::Hostile);
+    assert_eq!(attacks(world.invoke_actions(trader).unwrap().events), vec![(trader, player)]);
+}
+
+// a hunting wolf next to a sheep it already attacked, with the events of its next turn
+fn wolf_after_attack<F: Fn(&mut World, Idx)>(change: F) -> (World, Vec<Event>) {
+    let mut world = World::new([1, 2, 3, 4]);
+    let (level, wolf, sheep) = (world.next_id(), world.next_id(), world.next_id());
+    let template = CreatureTemplate { name: "wolf".to_string(), faction: "wolves".to_string(), ..CreatureTemplate::default() };
+    {
+        let level = world.add_level(Level::new(level, IPoint{x: 4, y: 1})).unwrap();
+        level.add_entity(member(sheep, "sheep"), IPoint{x: 0, y: 0}).unwrap();
+        level.add_entity(template.build(wolf), IPoint{x: 1, y: 0}).unwrap();
+    }
+    world.set_relation("wolves", "sheep", Relation::Hostile);
+    // it may wander off before it spots the sheep
+    let attack = Event::Attacked { source: wolf, target: sheep };
+    assert!((0..5).any(|_| world.invoke_actions(wolf).unwrap().events.contains(&attack)));
+    change(&mut world, sheep);
+    let events = world.invoke_actions(wolf).unwrap().events;
+    (world, events)
+}
+
+#[test]
+fn enemies_are_forgotten() {
+    // peace made from outside sticks, the wolf does not attack again and provoke the sheep
+    let (world, events) = wolf_after_attack(|world, _| world.set_relation("wolves", "sheep", Relation::Neutral));
+    assert!(!events.iter().any(|e| match e { Event::Attacked { .. } | Event::Provoked { .. } => true, _ => false }));
+    assert_eq!(world.relation(1, 2), Relation::Neutral);
+
+    // a dead sheep is left behind, the wolf wanders off
+    let (_, events) = wolf_after_attack(|world, sheep| world.get_mut_entity(sheep).unwrap().health_mut().unwrap().health = 0);
+    assert!(events.iter().any(|e| match e { Event::Moved { idx: 1, .. } => true, _ => false }), "{:?}", events);
+}
+
+// misses, hits and criticals of many attacks with the same seed, and the damage dealt
+fn roll_many(attacker: &Combat, defender: &Combat, seed: [u32; 4]) -> (u32, u32, u32, i32) {
+    let mut rand = XorShift::from_seed(seed);
+    let (mut misses, mut hits, mut criticals, mut total) = (0, 0, 0, 0);
+    for _ in 0..10000 {
+        match roll_attack(attacker, defender, &mut rand) {
+            Roll::Miss => misses += 1,
+            Roll::Hit { damage, critical } => {
+                if critical { criticals += 1 } else { hits += 1 }
+                total += damage;
+            }
+        }
+    }
+    (misses, hits, criticals, total)
+}
+
+#[test]
+fn combat_rolls() {
+    let dice: Vec<String> = ["2d6+1", "d4", "3", "1d8-2"].iter()
+        .map(|d| d.parse::<Dice>().unwrap().to_string())
+        .collect();
+    assert_eq!(dice, vec!["2d6+1", "1d4", "3", "1d8-2"]);
+    assert!("2x6".parse::<Dice>().is_err());
+    assert!(Templates::from_json(r#"[{"name": "rat", "damage": "1d4-3"}]"#).unwrap_err().to_string().contains("damage"));
+    for damage in ["1d4294967295", "70000d70000", "101d6"].iter() {
+        let json = format!(r#"[{{"name": "rat", "damage": "{}"}}]"#, damage);
+        assert!(Templates::from_json(&json).unwrap_err().to_string().contains("damage"));
+    }
+    let mut rat = CreatureTemplate { name: "rat".to_string(), ..CreatureTemplate::default() };
+    rat.damage = Dice { count: 100, sides: 1000, bonus: i32::max_value() };
+    assert!(rat.validate().unwrap_err().starts_with("damage"));
+    rat.damage = Dice { count: 100, sides: 1000, bonus: 0 };
+    assert_eq!(rat.validate(), Ok(()));
+
+    // nor can saves bring such dice
+    let mut save: serde_json::Value = serde_json::from_str(&GameState::new(IPoint{x: 60, y: 40}).unwrap().save().unwrap()).unwrap();
+    for (_, level) in save["state"]["game"]["levels"].as_object_mut().unwrap().iter_mut() {
+        for tile in level["tiles"].as_array_mut().unwrap() {
+            for entity in tile[1].as_array_mut().unwrap() {
+                if entity["components"]["combat"].is_object() {
+                    entity["components"]["combat"]["damage"] = serde_json::Value::from("1d4294967295");
+                }
+            }
+        }
+    }
+    match GameState::load(&save.to_string()) {
+        Err(GameError::InvalidSave { message }) => assert!(message.contains("damage"), "{}", message),
+        other => panic!("expected an invalid save, got {:?}", other.map(|s| s.status)),
+    }
+
+    // the default always hits for 1 and leaves rand alone
+    let mut rand = XorShift::from_seed([1, 2, 3, 4]);
+    assert_eq!(roll_attack(&Combat::default(), &Combat::default(), &mut rand), Roll::Hit { damage: 1, critical: false });
+    assert_eq!(rand.next_u32(), XorShift::from_seed([1, 2, 3, 4]).next_u32());
+
+    // 60% to hit, a tenth of the hits critical, 2d6+1-2 damage
+    let attacker = Combat { attack: 1, accuracy: 80, critical: 10, damage: "2d6".parse().unwrap(), ..Combat::default() };
+    let defender = Combat { defense: 2, evasion: 20, ..Combat::default() };
+    let rolls = roll_many(&attacker, &defender, [1, 2, 3, 4]);
+    assert_eq!(roll_many(&attacker, &defender, [1, 2, 3, 4]), rolls);
+    assert!(roll_many(&attacker, &defender, [4, 3, 2, 1]) != rolls);
+    let (misses, hits, criticals, total) = rolls;
+    assert!(misses > 3800 && misses < 4200);
+    assert!(criticals > 500 && criticals < 700);
+    // 6 on average for hits, 13 for criticals
+    let average = total as f64 / (hits + criticals) as f64;
+    assert!(average > 6.3 && average < 7.1);
+
+    // players read about the fights they see
+    let mut state = GameState::new(IPoint{x: 60, y: 40}).unwrap();
+    state.add_player().unwrap();
+    let first = state.players[0].player;
+    state.submit_command(Command::Wait).unwrap();
+    state.submit_command(Command::Attack { target: first }).unwrap();
+    // the same fight told from both sides, whatever the roll
+    let yours = state.players[1].get_messages().last().unwrap().clone();
+    let theirs = yours.replace("the player", "you").replacen("You miss", "The player misses", 1)
+        .replacen("You hit", "The player hits", 1).replacen("You critically hit", "The player critically hits", 1);
+    assert!(yours.starts_with("You "));
+    assert_eq!(state.players[0].get_messages().last().unwrap(), &theirs);
+}
+
+#[test]
+fn victory_spares_neutrals() {
+    let mut state = GameState::from_config(unpopulated(IPoint{x: 60, y: 40})).unwrap();
+    let player = state.players[0].player;
+    let position = state.game.get_entity(player).unwrap().position();
+    let (monster, trader) = (state.game.next_id(), state.game.next_id());
+    {
+        let bundle = state.game.templates().build("monster", monster).unwrap();
+        let level = state.game.get_mut_entity_level(player).unwrap();
+        let free: Vec<IPoint> = position.neumann_surrounding().into_iter().filter(|p| !level.is_blocking(*p)).collect();
+        level.add_entity(bundle, free[0]).unwrap();
+        level.add_entity(member(trader, "traders"), free[1]).unwrap();
+    }
+    state.game.get_mut_entity(monster).unwrap().health_mut().unwrap().health = 1;
+    for _ in 0..50 {
+        if state.game.get_entity(monster).unwrap().is_alive() {
+            state.submit_command(Command::Attack { target: monster }).unwrap();
+        }
+    }
+    assert_eq!(state.status, GameStatus::Won);
+    assert!(state.game.get_entity(trader).unwrap().is_alive());
 }
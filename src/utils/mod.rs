@@ -2,4 +2,7 @@ pub mod point;
 pub mod fpoint;
 pub mod ipoint;
 pub mod irange;
-pub mod pointrng;
\ No newline at end of file
+pub mod pointrng;
+pub mod pointmap;
+pub mod xorshift;
+pub mod fnv;
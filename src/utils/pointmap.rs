@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use utils::ipoint::IPoint;
+
+// json objects only take string keys, so maps keyed by points are stored as lists of pairs,
+// sorted row by row so the output does not depend on hash order
+
+fn sorted<V>(map: &HashMap<IPoint, V>) -> Vec<(&IPoint, &V)> {
+    let mut pairs: Vec<(&IPoint, &V)> = map.iter().collect();
+    pairs.sort_by_key(|(p, _v)| (p.y, p.x));
+    pairs
+}
+
+pub fn serialize<V, S>(map: &HashMap<IPoint, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where V: Serialize, S: Serializer {
+    sorted(map).serialize(serializer)
+}
+
+pub fn deserialize<'de, V, D>(deserializer: D) -> Result<HashMap<IPoint, V>, D::Error>
+    where V: Deserialize<'de>, D: Deserializer<'de> {
+    let pairs: Vec<(IPoint, V)> = Vec::deserialize(deserializer)?;
+    Ok(pairs.into_iter().collect())
+}
+
+pub mod nested {
+    use super::*;
+
+    pub fn serialize<K, V, S>(maps: &HashMap<K, HashMap<IPoint, V>>, serializer: S) -> Result<S::Ok, S::Error>
+        where K: Serialize + Ord + Hash, V: Serialize, S: Serializer {
+        let mut pairs: Vec<(&K, Vec<(&IPoint, &V)>)> = maps.iter()
+            .map(|(k, map)| (k, sorted(map)))
+            .collect();
+        pairs.sort_by_key(|(k, _map)| *k);
+        pairs.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<HashMap<K, HashMap<IPoint, V>>, D::Error>
+        where K: Deserialize<'de> + Eq + Hash, V: Deserialize<'de>, D: Deserializer<'de> {
+        let pairs: Vec<(K, Vec<(IPoint, V)>)> = Vec::deserialize(deserializer)?;
+        Ok(pairs.into_iter()
+            .map(|(k, map)| (k, map.into_iter().collect()))
+            .collect())
+    }
+}
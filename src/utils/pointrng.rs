@@ -1,4 +1,4 @@
-use rand::XorShiftRng;
+use utils::xorshift::XorShift;
 use rand::Rng;
 use utils::ipoint::IPoint;
 use utils::irange::IRange;
@@ -10,7 +10,7 @@ pub trait PointRng {
     }
 }
 
-impl PointRng for XorShiftRng {
+impl PointRng for XorShift {
     fn get_point(&mut self, start: IPoint, end: IPoint) -> IPoint {
         IPoint {
             x: self.gen_range(start.x, end.x),
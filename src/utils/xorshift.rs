@@ -0,0 +1,41 @@
+use std::num::Wrapping;
+use rand::Rng;
+use rand::SeedableRng;
+
+// same generator as rand::XorShiftRng, but with visible state so it can be saved
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct XorShift {
+    x: u32,
+    y: u32,
+    z: u32,
+    w: u32,
+}
+
+impl Rng for XorShift {
+    fn next_u32(&mut self) -> u32 {
+        let x = Wrapping(self.x);
+        let t = x ^ (x << 11);
+        self.x = self.y;
+        self.y = self.z;
+        self.z = self.w;
+        let w = Wrapping(self.w);
+        self.w = (w ^ (w >> 19) ^ (t ^ (t >> 8))).0;
+        self.w
+    }
+}
+
+impl SeedableRng<[u32; 4]> for XorShift {
+    fn reseed(&mut self, seed: [u32; 4]) {
+        *self = XorShift::from_seed(seed);
+    }
+
+    fn from_seed(seed: [u32; 4]) -> XorShift {
+        assert!(!seed.iter().all(|&x| x == 0), "XorShift seed can not be all zeros");
+        XorShift {
+            x: seed[0],
+            y: seed[1],
+            z: seed[2],
+            w: seed[3],
+        }
+    }
+}